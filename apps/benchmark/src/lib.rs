@@ -23,12 +23,26 @@ use thread::{append_task, spawn};
 
 use net::*;
 use stdio::log::info;
-use timer::get_time_ms;
+use timer::{get_time_ms, get_time_us};
 
 static IO_TIME: Lazy<ArrayQueue<usize>> = Lazy::new(|| ArrayQueue::new(120));
 
 const LOOP_SIZE: usize = 100;
 
+// trace 事件类型, 用 cargo decode 转为 CSV
+const EV_ALLOC_SMALL_US: u32 = 1;
+const EV_ALLOC_MIXED_US: u32 = 2;
+const EV_START_MS: u32 = 3;
+const EV_ECHO_MS: u32 = 4;
+
+fn define_events() {
+    trace::set_format(trace::Format::Binary);
+    trace::define(EV_ALLOC_SMALL_US, "alloc_small_us");
+    trace::define(EV_ALLOC_MIXED_US, "alloc_mixed_us");
+    trace::define(EV_START_MS, "start_ms");
+    trace::define(EV_ECHO_MS, "echo_ms");
+}
+
 // 计算密集型任务
 fn fib(n: i32) -> i32 {
     if n <= 1 {
@@ -38,6 +52,36 @@ fn fib(n: i32) -> i32 {
     }
 }
 
+const ALLOC_ROUNDS: usize = 10000;
+
+// 分配器基准: 协程 Box, Arc 大小的对象, 以及网络缓冲区
+fn alloc_bench() {
+    use alloc::sync::Arc;
+
+    let begin = get_time_us();
+    for i in 0..ALLOC_ROUNDS {
+        let f: Box<dyn Future<Output = ()> + Send> = Box::new(async move {
+            fib(i as i32 % 2);
+        });
+        let waker = Arc::new(i);
+        drop((f, waker));
+    }
+    let small = get_time_us() - begin;
+
+    let begin = get_time_us();
+    let mut live = Vec::with_capacity(64);
+    for i in 0..ALLOC_ROUNDS {
+        live.push(vec![0u8; 16 << (i % 8)]);
+        if live.len() == 64 {
+            live.clear();
+        }
+    }
+    let mixed = get_time_us() - begin;
+
+    trace::event(EV_ALLOC_SMALL_US, small as u64);
+    trace::event(EV_ALLOC_MIXED_US, mixed as u64);
+}
+
 async fn echo_client_one(sender: SocketHandle) {
     let mut tx = vec!['x' as u8; 1024];
     let mut rx = vec![0 as u8; 1024];
@@ -49,7 +93,7 @@ async fn echo_client_one(sender: SocketHandle) {
         .await
         .expect("conn broken");
     let end: usize = get_time_ms();
-    info!("CU {}", end - begin);
+    trace::event(EV_ECHO_MS, (end - begin) as u64);
     IO_TIME.push(end - begin);
     async_sock_close(sender).await;
 }
@@ -67,7 +111,7 @@ async fn echo_client_basic(sender: SocketHandle) {
             .expect("conn broken");
         let end = get_time_ms();
         IO_TIME.push(end - begin);
-        info!("{}", end - begin);
+        trace::event(EV_ECHO_MS, (end - begin) as u64);
     }
     async_sock_close(sender).await;
 }
@@ -78,8 +122,11 @@ pub async fn app_main() {
     let remote_endpoint = IpEndpoint::new(IpAddress::v4(47, 92, 33, 237), 6000);
     // let remote_endpoint = IpEndpoint::new(IpAddress::v4(192, 168, 1, 121), 6000);
 
+    define_events();
+    alloc_bench();
+
     let begin = get_time_ms();
-    info!("ALL {begin}");
+    trace::event(EV_START_MS, begin as u64);
 
     for _ in 0..100 {
         let _tid = spawn(
@@ -122,4 +169,21 @@ pub async fn app_main() {
         vec.len(),
         vec.iter().sum::<usize>() / vec.len()
     );
+
+    // 有磁盘时保存结果, 由 xtask qemu --extract 取回
+    match save_results(&vec).await {
+        Ok(()) | Err(fs::FsError::NoDevice) => {}
+        Err(e) => info!("save results: {e:?}"),
+    }
+}
+
+async fn save_results(io_time: &[usize]) -> core::result::Result<(), fs::FsError> {
+    use alloc::{format, string::String};
+    let mut csv = String::from("round,ms\n");
+    for (i, ms) in io_time.iter().enumerate() {
+        csv.push_str(&format!("{i},{ms}\n"));
+    }
+    let mut file = fs::create("/bench.csv").await?;
+    file.write(csv.as_bytes()).await?;
+    Ok(())
 }
@@ -0,0 +1,290 @@
+//! 交互式内核 shell：从控制台读一行命令并执行。
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::time::Duration;
+use executor::{async_wait, async_yield};
+use net::*;
+use stdio::{print, println};
+use thread::TaskState;
+use timer::get_time_us;
+
+const PROMPT: &str = "> ";
+/// ping 每次等待回复的时间
+const PING_TIMEOUT_US: usize = 1_000_000;
+/// connect 建立连接和等待回复的时间
+const CONNECT_TIMEOUT_US: usize = 3_000_000;
+
+const HELP: &str = "\
+commands:
+  help                       show this message
+  ps                         list threads
+  mem                        show heap statistics
+  netstat                    list tcp sockets
+  ifconfig                   show interface configuration
+  ping <ip> [count]          send icmp echo requests
+  connect <ip> <port> [msg]  open a tcp connection, send msg and print the reply
+  sleep <ms>                 sleep for a while
+  spawn fib <n>              compute fib(n) in a new thread
+  loglevel [spec]            show or set log levels, e.g. info,net=debug
+  shutdown                   power off";
+
+pub async fn app_main() {
+    let mut buf = [0u8; 128];
+    println!("kernel shell, type `help` for commands");
+    loop {
+        print!("{PROMPT}");
+        let len = stdio::async_read_line(&mut buf).await;
+        let Ok(line) = core::str::from_utf8(&buf[..len]) else {
+            continue;
+        };
+        let args: Vec<&str> = line.split_whitespace().collect();
+        if let Some((cmd, args)) = args.split_first() {
+            run(cmd, args).await;
+        }
+    }
+}
+
+async fn run(cmd: &str, args: &[&str]) {
+    match cmd {
+        "help" => println!("{HELP}"),
+        "ps" => ps(),
+        "mem" => mem(),
+        "netstat" => netstat(),
+        "ifconfig" => ifconfig(),
+        "ping" => match args {
+            [ip] => ping(ip, 4).await,
+            [ip, count] => match count.parse() {
+                Ok(count) => ping(ip, count).await,
+                Err(_) => println!("ping: bad count {count}"),
+            },
+            _ => println!("usage: ping <ip> [count]"),
+        },
+        "connect" => match args {
+            [ip, port, msg @ ..] => connect(ip, port, msg).await,
+            _ => println!("usage: connect <ip> <port> [msg]"),
+        },
+        "sleep" => match args {
+            [ms] => match ms.parse() {
+                Ok(ms) => async_wait(Duration::from_millis(ms)).await,
+                Err(_) => println!("sleep: bad duration {ms}"),
+            },
+            _ => println!("usage: sleep <ms>"),
+        },
+        "spawn" => match args {
+            ["fib", n] => match n.parse() {
+                Ok(n) => spawn_fib(n),
+                Err(_) => println!("spawn: bad number {n}"),
+            },
+            _ => println!("usage: spawn fib <n>"),
+        },
+        "loglevel" => match args {
+            [] => println!("{}", stdio::log_spec()),
+            [spec] => {
+                if let Err(e) = stdio::set_log_spec(spec) {
+                    println!("loglevel: {e}");
+                }
+            }
+            _ => println!("usage: loglevel [spec]"),
+        },
+        "shutdown" => thread::shutdown(false),
+        _ => println!("{cmd}: command not found"),
+    }
+}
+
+fn ps() {
+    println!(
+        "{:>5} {:>3} {:>4} {:>9} {:>5} {:>10}",
+        "TID", "IO", "PRI", "STATE", "CORO", "CPU(ms)"
+    );
+    for task in thread::tasks() {
+        let state = match task.state {
+            TaskState::Running => "running",
+            TaskState::Ready => "ready",
+            TaskState::Sleeping => "sleeping",
+        };
+        println!(
+            "{:>5} {:>3} {:>4} {:>9} {:>5} {:>10}",
+            task.tid,
+            if task.io { "y" } else { "n" },
+            task.priority,
+            state,
+            task.coroutines,
+            task.cpu_time_us / 1000
+        );
+    }
+}
+
+fn mem() {
+    let stats = mem::heap_stats();
+    println!("total:        {} bytes", stats.total);
+    println!("in use:       {} bytes", stats.in_use);
+    println!("peak:         {} bytes", stats.peak);
+    println!("largest free: {} bytes", stats.largest_free);
+    println!(
+        "allocs: {}, deallocs: {}, failures: {}",
+        stats.allocs, stats.deallocs, stats.failures
+    );
+}
+
+fn netstat() {
+    println!(
+        "{:>6} {:>22} {:>22} {:>12}",
+        "SOCK", "LOCAL", "REMOTE", "STATE"
+    );
+    for sock in sys_sock_list() {
+        println!(
+            "{:>6?} {:>22} {:>22} {:>12}",
+            sock.handle, sock.local, sock.remote, sock.state
+        );
+    }
+}
+
+fn ifconfig() {
+    let info = sys_if_info();
+    let [a, b, c, d, e, f] = info.mac;
+    println!("ether   {a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{f:02x}");
+    match info.cidr {
+        Some(cidr) => println!("inet    {cidr}"),
+        None => println!("inet    (waiting for dhcp)"),
+    }
+    if let Some(gateway) = info.gateway {
+        println!("gateway {gateway}");
+    }
+}
+
+async fn ping(ip: &str, count: u16) {
+    let Ok(addr) = ip.parse::<Ipv4Address>() else {
+        println!("ping: bad address {ip}");
+        return;
+    };
+    let ident = get_time_us() as u16;
+    let sock = match sys_icmp_create(ident) {
+        Ok(sock) => sock,
+        Err(e) => {
+            println!("ping: {e}");
+            return;
+        }
+    };
+    let mut received = 0;
+    'ping: for seq in 0..count {
+        let start = get_time_us();
+        if let Err(e) = sys_ping_send(sock, ident, addr, seq, b"crate ping") {
+            println!("ping: {e}");
+            break;
+        }
+        loop {
+            match sys_ping_recv(sock, ident) {
+                Ok(Some((from, seq_no))) if seq_no == seq => {
+                    let rtt = get_time_us() - start;
+                    println!(
+                        "reply from {from}: seq={seq} time={}.{:03}ms",
+                        rtt / 1000,
+                        rtt % 1000
+                    );
+                    received += 1;
+                    break;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    println!("ping: {e}");
+                    break 'ping;
+                }
+            }
+            if get_time_us() - start >= PING_TIMEOUT_US {
+                println!("seq={seq} timeout");
+                break;
+            }
+            async_yield().await;
+        }
+    }
+    sys_icmp_release(sock);
+    println!("{count} sent, {received} received");
+}
+
+async fn connect(ip: &str, port: &str, msg: &[&str]) {
+    let (Ok(addr), Ok(port)) = (ip.parse::<Ipv4Address>(), port.parse::<u16>()) else {
+        println!("connect: bad endpoint {ip} {port}");
+        return;
+    };
+    let sock = sys_sock_create();
+    if let Err(e) = sys_sock_connect(sock, (IpAddress::Ipv4(addr), port)) {
+        println!("connect: {e}");
+        sys_sock_release(sock);
+        return;
+    }
+    // 不用 async_connect：连接被拒绝时它不会返回
+    let start = get_time_us();
+    loop {
+        match sys_sock_status(sock).state {
+            TcpState::Established => break,
+            TcpState::Closed => {
+                println!("connect: refused");
+                sys_sock_release(sock);
+                return;
+            }
+            _ if get_time_us() - start >= CONNECT_TIMEOUT_US => {
+                println!("connect: timeout");
+                sys_sock_release(sock);
+                return;
+            }
+            _ => async_yield().await,
+        }
+    }
+    println!("connected to {addr}:{port}");
+    if !msg.is_empty() {
+        let mut line = msg.join(" ").into_bytes();
+        line.push(b'\n');
+        match async_send(sock, &mut line).await {
+            Ok(_) => {
+                let mut buf = [0u8; 512];
+                let start = get_time_us();
+                loop {
+                    match sys_sock_recv(sock, &mut buf) {
+                        Ok(0) if get_time_us() - start < CONNECT_TIMEOUT_US => async_yield().await,
+                        Ok(0) => {
+                            println!("connect: no reply");
+                            break;
+                        }
+                        Ok(n) => {
+                            print!("{}", alloc::string::String::from_utf8_lossy(&buf[..n]));
+                            println!();
+                            break;
+                        }
+                        Err(e) => {
+                            println!("connect: {e}");
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) => println!("connect: {e}"),
+        }
+    }
+    async_sock_close(sock).await;
+    println!("closed");
+}
+
+// 计算密集型任务
+fn fib(n: u32) -> u64 {
+    if n <= 1 {
+        n as u64
+    } else {
+        fib(n - 1) + fib(n - 2)
+    }
+}
+
+fn spawn_fib(n: u32) {
+    let tid = thread::spawn(
+        async move {
+            let start = get_time_us();
+            let result = fib(n);
+            println!("fib({n}) = {result}, {} ms", (get_time_us() - start) / 1000);
+        },
+        false,
+    );
+    println!("spawned thread {tid}");
+}
@@ -66,6 +66,35 @@ impl<T: PartialOrd, A: Allocator> Heap<T, A> {
         self.sift_up(i);
     }
 
+    /// Iterate over the items in the heap in no particular order.
+    pub fn iter(&self) -> core::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    /// Mutably iterate over the items in the heap in no particular order.
+    ///
+    /// The caller must not change the fields used for ordering.
+    pub fn iter_mut(&mut self) -> core::slice::IterMut<'_, T> {
+        self.data.iter_mut()
+    }
+
+    /// Remove and return the first item matching `f`,
+    /// or None if no item matches.
+    pub fn remove_by<F>(&mut self, f: F) -> Option<T>
+    where
+        F: Fn(&T) -> bool,
+    {
+        let i = self.data.iter().position(f)?;
+        let last = self.data.len() - 1;
+        self.data.as_mut_slice().swap(i, last);
+        let result = self.data.remove(last);
+        if i < self.data.len() {
+            self.sift_down(i);
+            self.sift_up(i);
+        }
+        Some(result)
+    }
+
     pub fn insert_vec(&mut self, values: Vec<T>) {
         for value in values.into_iter() {
             self.push(value);
@@ -157,4 +186,21 @@ mod tests {
         assert_eq!(Some(9), heap.pop());
         assert_eq!(None, heap.pop());
     }
+
+    #[test]
+    fn test_remove_by() {
+        let mut heap: Heap<usize> = Heap::new();
+        for i in [5, 3, 8, 1, 9, 2, 7] {
+            heap.push(i);
+        }
+        assert_eq!(Some(8), heap.remove_by(|&i| i == 8));
+        assert_eq!(Some(1), heap.remove_by(|&i| i == 1));
+        assert_eq!(None, heap.remove_by(|&i| i == 4));
+        assert_eq!(Some(2), heap.pop());
+        assert_eq!(Some(3), heap.pop());
+        assert_eq!(Some(5), heap.pop());
+        assert_eq!(Some(7), heap.pop());
+        assert_eq!(Some(9), heap.pop());
+        assert_eq!(None, heap.pop());
+    }
 }
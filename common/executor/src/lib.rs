@@ -27,7 +27,12 @@ pub trait Executor: Sync + Send {
 
     fn sys_yield(&self);
 
-    fn sys_register_irq(&self, cx: &mut Context<'_>, irq: IRQ);
+    /// 中断号为 irq 的中断已到来的次数
+    fn sys_irq_seq(&self, irq: IRQ) -> usize;
+
+    /// 登记 cx 的 waker, 中断号为 irq 的中断次数不再等于 seq 时唤醒;
+    /// 同一中断号可以有多个等待者
+    fn sys_register_irq(&self, cx: &mut Context<'_>, irq: IRQ, seq: usize);
 }
 
 /// EXECUTOR
@@ -6,24 +6,21 @@ use core::{
     task::{Context, Poll},
 };
 
-#[derive(Clone, Copy)]
-pub enum IRQ {
-    UART0_IRQ,
-    VIRTIO0_IRQ,
-    E1000_IRQ,
-}
+/// 外部中断号, 由平台的中断控制器定义
+pub type IRQ = u32;
 
-struct IRQ_EVENT {
+/// 等待中断号为 irq 的下一次中断
+///
+/// 创建时记录中断次数, 次数增加后才完成, 被其它原因唤醒时继续等待
+pub struct IRQ_EVENT {
     irq: IRQ,
-    ready: bool,
+    seq: usize,
 }
 
 impl IRQ_EVENT {
     pub fn new(irq: IRQ) -> Self {
-        Self {
-            irq: irq,
-            ready: false,
-        }
+        let seq = EXECUTOR.wait().sys_irq_seq(irq);
+        Self { irq, seq }
     }
 }
 
@@ -31,17 +28,22 @@ impl Future for IRQ_EVENT {
     type Output = ();
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let irq = self.irq;
-        if self.ready {
+        let this = self.get_mut();
+        let executor = EXECUTOR.wait();
+        if executor.sys_irq_seq(this.irq) != this.seq {
             Poll::Ready(())
         } else {
-            self.get_mut().ready = true;
-            EXECUTOR.wait().sys_register_irq(cx, irq);
+            // 登记期间中断到来时平台会立即唤醒
+            executor.sys_register_irq(cx, this.irq, this.seq);
             Poll::Pending
         }
     }
 }
 
-pub async fn async_wait_irq(irq: IRQ) {
-    IRQ_EVENT::new(irq).await
+/// 等待中断号为 irq 的外部中断
+///
+/// 调用时立即记录中断次数, 返回的 future 在此后有新的中断到来时完成,
+/// 即使 future 在中断之后才第一次被轮询
+pub fn async_wait_irq(irq: IRQ) -> IRQ_EVENT {
+    IRQ_EVENT::new(irq)
 }
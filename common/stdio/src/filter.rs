@@ -0,0 +1,197 @@
+//! 日志级别过滤：全局级别加按模块的指令，如 `info,net=debug,qemu_virt::tasks=trace`。
+
+use alloc::{string::String, vec::Vec};
+use core::{cmp::Reverse, fmt, str::FromStr};
+use log::LevelFilter;
+use spin::RwLock;
+
+/// 日志过滤规则。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogSpec {
+    default: LevelFilter,
+    /// (模块路径, 级别)，按路径长度从长到短排列
+    directives: Vec<(String, LevelFilter)>,
+}
+
+/// 解析日志规则失败。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogSpecError(String);
+
+impl fmt::Display for LogSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bad log directive `{}`", self.0)
+    }
+}
+
+impl LogSpec {
+    /// 只有全局级别的规则。
+    pub const fn new(default: LevelFilter) -> Self {
+        Self {
+            default,
+            directives: Vec::new(),
+        }
+    }
+
+    /// 全局级别。
+    pub fn default_level(&self) -> LevelFilter {
+        self.default
+    }
+
+    /// 设置全局级别，不影响模块指令。
+    pub fn set_default_level(&mut self, level: LevelFilter) {
+        self.default = level;
+    }
+
+    /// 设置一个模块及其子模块的级别。
+    pub fn set_module_level(&mut self, module: &str, level: LevelFilter) {
+        match self.directives.iter_mut().find(|(m, _)| m == module) {
+            Some((_, old)) => *old = level,
+            None => {
+                self.directives.push((module.into(), level));
+                self.directives.sort_by_key(|(m, _)| Reverse(m.len()));
+            }
+        }
+    }
+
+    /// `target` 使用的级别：最长的匹配模块指令，没有则为全局级别。
+    pub fn level(&self, target: &str) -> LevelFilter {
+        self.directives
+            .iter()
+            .find(|(module, _)| {
+                target
+                    .strip_prefix(module.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    /// 所有规则中最详细的级别，用作 `log::max_level`。
+    pub fn max_level(&self) -> LevelFilter {
+        self.directives
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+impl FromStr for LogSpec {
+    type Err = LogSpecError;
+
+    /// 逗号分隔的指令，`level` 设置全局级别，`module=level` 设置模块级别。
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut spec = Self::new(LevelFilter::Trace);
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let err = || LogSpecError(directive.into());
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    let module = module.trim();
+                    if module.is_empty() {
+                        return Err(err());
+                    }
+                    let level = LevelFilter::from_str(level.trim()).map_err(|_| err())?;
+                    spec.set_module_level(module, level);
+                }
+                None => spec.default = LevelFilter::from_str(directive).map_err(|_| err())?,
+            }
+        }
+        Ok(spec)
+    }
+}
+
+impl fmt::Display for LogSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.default.as_str().to_ascii_lowercase())?;
+        for (module, level) in self.directives.iter().rev() {
+            write!(f, ",{module}={}", level.as_str().to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+/// 当前规则。
+///
+/// 日志可能在持有写锁的线程被打断时输出，所以读取一律用 `try_read`。
+static SPEC: RwLock<LogSpec> = RwLock::new(LogSpec::new(LevelFilter::Trace));
+
+pub(crate) fn enabled(metadata: &log::Metadata) -> bool {
+    match SPEC.try_read() {
+        Some(spec) => metadata.level() <= spec.level(metadata.target()),
+        None => metadata.level() <= log::max_level(),
+    }
+}
+
+/// 修改当前规则。
+pub(crate) fn update(f: impl FnOnce(&mut LogSpec)) {
+    let mut spec = SPEC.write();
+    f(&mut spec);
+    log::set_max_level(spec.max_level());
+}
+
+pub(crate) fn current() -> LogSpec {
+    SPEC.read().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_level() {
+        let spec: LogSpec = "info,net=debug,qemu_virt::tasks=trace".parse().unwrap();
+        assert_eq!(LevelFilter::Info, spec.default_level());
+        assert_eq!(LevelFilter::Debug, spec.level("net"));
+        assert_eq!(LevelFilter::Debug, spec.level("net::ethernet"));
+        assert_eq!(LevelFilter::Trace, spec.level("qemu_virt::tasks"));
+        assert_eq!(LevelFilter::Trace, spec.level("qemu_virt::tasks::dump"));
+        assert_eq!(LevelFilter::Info, spec.level("qemu_virt"));
+        assert_eq!(LevelFilter::Info, spec.level("qemu_virt::trap"));
+        assert_eq!(LevelFilter::Trace, spec.max_level());
+    }
+
+    #[test]
+    fn test_prefix_boundary() {
+        let spec: LogSpec = "warn,net=debug".parse().unwrap();
+        // 只在 `::` 处匹配
+        assert_eq!(LevelFilter::Warn, spec.level("netx"));
+        assert_eq!(LevelFilter::Warn, spec.level("network::tcp"));
+        assert_eq!(LevelFilter::Warn, spec.level("ne"));
+    }
+
+    #[test]
+    fn test_longest_prefix() {
+        let mut spec: LogSpec = "qemu_virt::tasks=trace,qemu_virt=error".parse().unwrap();
+        assert_eq!(LevelFilter::Trace, spec.level("qemu_virt::tasks"));
+        assert_eq!(LevelFilter::Error, spec.level("qemu_virt::mm"));
+        spec.set_module_level("qemu_virt", LevelFilter::Off);
+        assert_eq!(LevelFilter::Off, spec.level("qemu_virt::mm"));
+        assert_eq!(
+            "trace,qemu_virt=off,qemu_virt::tasks=trace",
+            alloc::format!("{spec}")
+        );
+    }
+
+    #[test]
+    fn test_bad_directives() {
+        // 错误中给出出错的那条指令
+        for (spec, bad) in [
+            ("loud", "loud"),
+            ("net=", "net="),
+            ("=debug", "=debug"),
+            ("info,net=verbose", "net=verbose"),
+            ("info,net=debug=trace", "net=debug=trace"),
+        ] {
+            assert_eq!(
+                Err(LogSpecError(bad.into())),
+                spec.parse::<LogSpec>().map(|_| ())
+            );
+        }
+        // 空指令被忽略
+        let spec: LogSpec = " debug , ,net = warn ".parse().unwrap();
+        assert_eq!(LevelFilter::Debug, spec.default_level());
+        assert_eq!(LevelFilter::Warn, spec.level("net"));
+        assert_eq!(
+            LevelFilter::Trace,
+            "".parse::<LogSpec>().unwrap().default_level()
+        );
+    }
+}
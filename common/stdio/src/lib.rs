@@ -3,10 +3,18 @@
 #![no_std]
 #![deny(warnings, missing_docs)]
 
+extern crate alloc;
+
+mod filter;
+mod sink;
+
 use core::{
-    fmt::{Arguments, Write},
-    str::FromStr,
+    fmt::{self, Arguments, Write},
+    future::poll_fn,
+    task::{Context, Poll},
 };
+pub use filter::{LogSpec, LogSpecError};
+pub use sink::{console_task, flush, force_sync};
 use spin::Once;
 
 /// 向用户提供 `log`。
@@ -29,6 +37,30 @@ pub trait Stdio: Sync {
 
     /// 从控制台读取一个字符。
     fn get_char(&self) -> u8;
+
+    /// 异步读取一个字符，没有输入时登记 `cx` 的 waker，收到输入时唤醒。
+    ///
+    /// 默认实现直接调用阻塞的 `get_char`。
+    #[inline]
+    fn poll_char(&self, _cx: &mut Context<'_>) -> Poll<u8> {
+        Poll::Ready(self.get_char())
+    }
+
+    /// 日志记录的时间戳（微秒），`None` 时不打印。
+    #[inline]
+    fn time_us(&self) -> Option<usize> {
+        None
+    }
+
+    /// 输出日志的线程号，不在线程中时为 `None`。
+    #[inline]
+    fn tid(&self) -> Option<usize> {
+        None
+    }
+
+    /// [`force_sync`] 时调用：之后的输出不能等待控制台的锁，持锁的一方可能不会再运行。
+    #[inline]
+    fn force_sync(&self) {}
 }
 
 /// 库找到输出的方法：保存一个对象引用，这是一种单例。
@@ -40,10 +72,44 @@ pub fn init(console: &'static dyn Stdio) {
     log::set_logger(&Logger).unwrap();
 }
 
-/// 根据环境变量设置日志级别。
+/// 根据环境变量设置日志规则，格式见 [`set_log_spec`]，缺省或格式错误时输出全部日志。
+///
+/// 格式错误时输出一条警告，需要在 [`init`] 之后调用。
 pub fn set_log_level(env: Option<&str>) {
-    use log::LevelFilter as Lv;
-    log::set_max_level(env.and_then(|s| Lv::from_str(s).ok()).unwrap_or(Lv::Trace));
+    let all = LogSpec::new(log::LevelFilter::Trace);
+    let (spec, err) = match env.map(str::parse::<LogSpec>) {
+        Some(Ok(spec)) => (spec, None),
+        Some(Err(e)) => (all, Some(e)),
+        None => (all, None),
+    };
+    filter::update(|cur| *cur = spec);
+    if let Some(e) = err {
+        log::warn!("LOG: {e}, fall back to trace");
+    }
+}
+
+/// 运行时替换日志规则，如 `info,net=debug,qemu_virt::tasks=trace`。
+///
+/// 单独的级别设置全局级别，`module=level` 设置模块及其子模块的级别。
+pub fn set_log_spec(spec: &str) -> Result<(), LogSpecError> {
+    let spec = spec.parse::<LogSpec>()?;
+    filter::update(|cur| *cur = spec);
+    Ok(())
+}
+
+/// 运行时修改全局级别，保留模块指令。
+pub fn set_default_level(level: log::LevelFilter) {
+    filter::update(|spec| spec.set_default_level(level));
+}
+
+/// 运行时修改一个模块的级别。
+pub fn set_module_level(module: &str, level: log::LevelFilter) {
+    filter::update(|spec| spec.set_module_level(module, level));
+}
+
+/// 当前的日志规则。
+pub fn log_spec() -> LogSpec {
+    filter::current()
 }
 
 /// 打印一些测试信息。
@@ -60,10 +126,15 @@ pub fn test_log() {
 /// 打印。
 ///
 /// 给宏用的，用户不会直接调它。
+/// 控制台任务运行后放入缓冲区，否则直接写到控制台。
 #[doc(hidden)]
 #[inline]
 pub fn _print(args: Arguments) {
-    Logger.write_fmt(args).unwrap();
+    if sink::buffered() {
+        sink::print(args);
+    } else {
+        Logger.write_fmt(args).unwrap();
+    }
 }
 
 /// 读取。
@@ -72,6 +143,41 @@ pub fn get_char() -> u8 {
     CONSOLE.wait().get_char()
 }
 
+/// 异步读取一个字符。
+pub async fn async_getchar() -> u8 {
+    poll_fn(|cx| CONSOLE.wait().poll_char(cx)).await
+}
+
+/// 异步读取一行到 `buf`，回显输入并处理退格，返回不含换行符的长度。
+///
+/// 只接受可打印的 ASCII 字符，`buf` 满时提前返回。
+pub async fn async_read_line(buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    while len < buf.len() {
+        match async_getchar().await {
+            b'\r' | b'\n' => {
+                println!();
+                break;
+            }
+            // 退格或 DEL
+            0x08 | 0x7f => {
+                if len > 0 {
+                    len -= 1;
+                    print!("\x08 \x08");
+                }
+            }
+            c if c.is_ascii_graphic() || c == b' ' => {
+                buf[len] = c;
+                len += 1;
+                // 经过缓冲区，保持与提示符的顺序
+                print!("{}", c as char);
+            }
+            _ => {}
+        }
+    }
+    len
+}
+
 /// 格式化打印。
 #[macro_export]
 macro_rules! print {
@@ -84,10 +190,10 @@ macro_rules! print {
 #[macro_export]
 macro_rules! println {
     () => ($crate::print!("\n"));
-    ($($arg:tt)*) => {{
-        $crate::_print(core::format_args!($($arg)*));
-        $crate::println!();
-    }}
+    // 和换行一起输出，缓冲输出时作为一条记录
+    ($($arg:tt)*) => {
+        $crate::_print(core::format_args!("{}\n", core::format_args!($($arg)*)));
+    }
 }
 
 struct Logger;
@@ -100,9 +206,33 @@ impl Write for Logger {
     }
 }
 
+/// 日志时间戳，秒.微秒
+struct Stamp(Option<usize>);
+
+impl fmt::Display for Stamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(us) => write!(f, "[{:>5}.{:06}] ", us / 1_000_000, us % 1_000_000),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 线程号，调度器等不在线程中的代码显示为 `-`
+struct Tid(Option<usize>);
+
+impl fmt::Display for Tid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(tid) => write!(f, "[t{tid:<3}]"),
+            None => write!(f, "[t-  ]"),
+        }
+    }
+}
+
 impl log::Log for Logger {
-    fn enabled(&self, _metadata: &log::Metadata) -> bool {
-        true
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        filter::enabled(metadata)
     }
 
     fn log(&self, record: &log::Record) {
@@ -117,9 +247,13 @@ impl log::Log for Logger {
             Debug => 32,
             Trace => 90,
         };
+        let console = CONSOLE.wait();
         println!(
-            "\x1b[{color_code}m[{:>5}] {}\x1b[0m",
+            "\x1b[{color_code}m{}[{:>5}] {} {}: {}\x1b[0m",
+            Stamp(console.time_us()),
             record.level(),
+            Tid(console.tid()),
+            record.module_path().unwrap_or(record.target()),
             record.args(),
         );
     }
@@ -0,0 +1,220 @@
+//! 缓冲输出：每条记录先格式化到栈上，再整体放入无锁环形缓冲区，由控制台任务写出。
+//!
+//! 环形缓冲区由定长槽组成，一条记录一次原子地预留连续的槽，所以不同线程的记录不会交错。
+//! 缓冲区满时丢弃记录并计数。
+
+use core::{
+    cell::UnsafeCell,
+    fmt::{self, Arguments, Write},
+    future::poll_fn,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::{Poll, Waker},
+};
+use spin::Mutex;
+
+const SLOTS: usize = 128;
+const SLOT_SIZE: usize = 128;
+/// 一次预留的最大长度，超过的记录分几次放入
+const RECORD_SIZE: usize = 512;
+
+struct Slot {
+    ready: AtomicBool,
+    len: AtomicUsize,
+    data: UnsafeCell<[u8; SLOT_SIZE]>,
+}
+
+impl Slot {
+    const fn new() -> Self {
+        Self {
+            ready: AtomicBool::new(false),
+            len: AtomicUsize::new(0),
+            data: UnsafeCell::new([0; SLOT_SIZE]),
+        }
+    }
+}
+
+struct Ring {
+    slots: [Slot; SLOTS],
+    /// 下一个要写出的槽，只有持有 `DRAINING` 的一方修改
+    head: AtomicUsize,
+    /// 下一个可预留的槽
+    tail: AtomicUsize,
+}
+
+// 槽的数据只由预留到它的写者和 ready 之后的读者访问
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    /// 预留 `n` 个连续的槽，返回第一个槽的序号
+    fn reserve(&self, n: usize) -> Option<usize> {
+        let mut tail = self.tail.load(Ordering::Relaxed);
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            if tail + n - head > SLOTS {
+                return None;
+            }
+            match self.tail.compare_exchange_weak(
+                tail,
+                tail + n,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(tail),
+                Err(cur) => tail = cur,
+            }
+        }
+    }
+
+    fn push(&self, bytes: &[u8]) -> bool {
+        let n = bytes.len().div_ceil(SLOT_SIZE);
+        let Some(start) = self.reserve(n) else {
+            return false;
+        };
+        for (i, chunk) in bytes.chunks(SLOT_SIZE).enumerate() {
+            let slot = &self.slots[(start + i) % SLOTS];
+            let data = unsafe { &mut *slot.data.get() };
+            data[..chunk.len()].copy_from_slice(chunk);
+            slot.len.store(chunk.len(), Ordering::Relaxed);
+            slot.ready.store(true, Ordering::Release);
+        }
+        true
+    }
+
+    /// 按顺序写出已完成的槽，遇到还在写的槽就停下
+    fn drain(&self, mut out: impl FnMut(&[u8])) {
+        loop {
+            let head = self.head.load(Ordering::Relaxed);
+            let slot = &self.slots[head % SLOTS];
+            if !slot.ready.load(Ordering::Acquire) {
+                break;
+            }
+            let len = slot.len.load(Ordering::Relaxed);
+            let data = unsafe { &*slot.data.get() };
+            out(&data[..len]);
+            slot.ready.store(false, Ordering::Relaxed);
+            self.head.store(head + 1, Ordering::Release);
+        }
+    }
+
+    fn has_ready(&self) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        self.slots[head % SLOTS].ready.load(Ordering::Acquire)
+    }
+}
+
+static RING: Ring = Ring {
+    slots: [const { Slot::new() }; SLOTS],
+    head: AtomicUsize::new(0),
+    tail: AtomicUsize::new(0),
+};
+
+/// 是否经过缓冲区输出，控制台任务启动后打开
+static BUFFERED: AtomicBool = AtomicBool::new(false);
+/// 同一时间只有一方写出缓冲区
+static DRAINING: AtomicBool = AtomicBool::new(false);
+/// 缓冲区满时丢弃的记录数
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+/// 控制台任务的 waker
+static WAKER: Mutex<Option<Waker>> = Mutex::new(None);
+
+#[inline]
+pub(crate) fn buffered() -> bool {
+    BUFFERED.load(Ordering::Acquire)
+}
+
+/// 在栈上格式化一条记录
+struct Record {
+    buf: [u8; RECORD_SIZE],
+    len: usize,
+}
+
+impl Record {
+    fn commit(&mut self) {
+        if self.len > 0 && !RING.push(&self.buf[..self.len]) {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+        self.len = 0;
+    }
+}
+
+impl Write for Record {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut bytes = s.as_bytes();
+        while !bytes.is_empty() {
+            if self.len == RECORD_SIZE {
+                self.commit();
+            }
+            let n = bytes.len().min(RECORD_SIZE - self.len);
+            self.buf[self.len..][..n].copy_from_slice(&bytes[..n]);
+            self.len += n;
+            bytes = &bytes[n..];
+        }
+        Ok(())
+    }
+}
+
+/// 格式化一条记录放入缓冲区，唤醒控制台任务
+pub(crate) fn print(args: Arguments) {
+    let mut record = Record {
+        buf: [0; RECORD_SIZE],
+        len: 0,
+    };
+    let _ = record.write_fmt(args);
+    record.commit();
+    // 拿不到锁说明控制台任务正在登记 waker，它登记后会再检查缓冲区
+    if let Some(mut waker) = WAKER.try_lock() {
+        if let Some(waker) = waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// 写出缓冲区中已完成的记录，另一方正在写出时直接返回
+pub fn flush() {
+    if DRAINING.swap(true, Ordering::Acquire) {
+        return;
+    }
+    drain();
+    DRAINING.store(false, Ordering::Release);
+}
+
+fn drain() {
+    let console = crate::CONSOLE.wait();
+    let dropped = DROPPED.swap(0, Ordering::Relaxed);
+    RING.drain(|bytes| match core::str::from_utf8(bytes) {
+        Ok(s) => console.put_str(s),
+        // 多字节字符被槽边界切开
+        Err(_) => bytes.iter().for_each(|c| console.put_char(*c)),
+    });
+    if dropped > 0 {
+        let _ = crate::Logger.write_fmt(format_args!("[console: {dropped} records dropped]\n"));
+    }
+}
+
+/// 回到同步输出并写出缓冲区中已完成的记录，给 panic 和关机使用。
+///
+/// 不等待正在写出的一方：panic 之后它可能不会再运行。
+pub fn force_sync() {
+    if let Some(console) = crate::CONSOLE.get() {
+        console.force_sync();
+    }
+    BUFFERED.store(false, Ordering::SeqCst);
+    DRAINING.store(true, Ordering::SeqCst);
+    drain();
+    DRAINING.store(false, Ordering::Release);
+}
+
+/// 控制台任务：打开缓冲输出，并在有新记录时写出。
+pub async fn console_task() {
+    BUFFERED.store(true, Ordering::Release);
+    poll_fn(|cx| {
+        flush();
+        *WAKER.lock() = Some(cx.waker().clone());
+        if RING.has_ready() {
+            // 登记之前写入的记录
+            cx.waker().wake_by_ref();
+        }
+        Poll::<()>::Pending
+    })
+    .await
+}
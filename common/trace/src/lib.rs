@@ -0,0 +1,291 @@
+//! 结构化事件记录，代替手工抄写 `info!` 输出的基准测试数据。
+//!
+//! 每个事件输出为一行，以 `@T` 开头，可以和普通日志混在一起，由 `cargo xtask decode` 挑出并转为 CSV：
+//!
+//! - `@TK,<kind>,<name>`：事件类型的名字
+//! - `@TC,<time_us>,<tid>,<cid>,<kind>,<payload>`：CSV 格式的事件，没有线程或协程时为 -1
+//! - `@TB<hex>`：二进制格式的事件，见 [`Record::encode`]
+
+#![no_std]
+
+use core::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicU8, Ordering},
+};
+use spin::Once;
+
+/// 平台提供时间、当前线程和协程，以及输出的通道。
+pub trait Tracer: Sync {
+    /// 当前时间（微秒）
+    fn time_us(&self) -> usize;
+
+    /// 当前线程
+    fn tid(&self) -> Option<usize> {
+        None
+    }
+
+    /// 当前协程
+    fn cid(&self) -> Option<usize> {
+        None
+    }
+
+    /// 输出一行记录，包含换行符
+    fn emit(&self, line: &str);
+}
+
+static TRACER: Once<&'static dyn Tracer> = Once::new();
+
+pub fn init(tracer: &'static dyn Tracer) {
+    TRACER.call_once(|| tracer);
+}
+
+/// 事件的输出格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Format {
+    /// 不输出
+    Off = 0,
+    /// 可读的 CSV 行
+    Csv = 1,
+    /// 十六进制编码的定长二进制记录
+    Binary = 2,
+}
+
+static FORMAT: AtomicU8 = AtomicU8::new(Format::Off as u8);
+
+pub fn set_format(format: Format) {
+    FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+pub fn format() -> Format {
+    match FORMAT.load(Ordering::Relaxed) {
+        1 => Format::Csv,
+        2 => Format::Binary,
+        _ => Format::Off,
+    }
+}
+
+/// 一个事件
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub time_us: u64,
+    pub tid: Option<u32>,
+    pub cid: Option<u32>,
+    pub kind: u32,
+    pub payload: u64,
+}
+
+/// 二进制记录中表示没有线程或协程
+const NONE: u32 = u32::MAX;
+
+/// 可以记录的最大编号, 更大的编号被截到这里, 避免与 NONE 混淆
+pub const MAX_ID: u32 = NONE - 1;
+
+#[inline]
+fn clamp_id(id: usize) -> u32 {
+    id.min(MAX_ID as usize) as u32
+}
+
+impl Record {
+    /// 二进制记录的长度
+    pub const SIZE: usize = 28;
+
+    /// 小端序：time_us u64 | tid u32 | cid u32 | kind u32 | payload u64
+    ///
+    /// 编号 `u32::MAX` 用来表示没有线程或协程，解码后为 `None`；
+    /// 超过 [`MAX_ID`] 的编号被截断为 `MAX_ID`。
+    pub fn encode(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0u8; Self::SIZE];
+        buf[0..8].copy_from_slice(&self.time_us.to_le_bytes());
+        let id = |v: Option<u32>| v.map_or(NONE, |v| v.min(MAX_ID));
+        buf[8..12].copy_from_slice(&id(self.tid).to_le_bytes());
+        buf[12..16].copy_from_slice(&id(self.cid).to_le_bytes());
+        buf[16..20].copy_from_slice(&self.kind.to_le_bytes());
+        buf[20..28].copy_from_slice(&self.payload.to_le_bytes());
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let buf: &[u8; Self::SIZE] = buf.try_into().ok()?;
+        let u32_at = |i: usize| u32::from_le_bytes(buf[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(buf[i..i + 8].try_into().unwrap());
+        let id = |v: u32| (v != NONE).then_some(v);
+        Some(Self {
+            time_us: u64_at(0),
+            tid: id(u32_at(8)),
+            cid: id(u32_at(12)),
+            kind: u32_at(16),
+            payload: u64_at(20),
+        })
+    }
+}
+
+/// 输出一行的栈上缓冲区，超长的部分被截断
+struct Line {
+    buf: [u8; 128],
+    len: usize,
+}
+
+impl Line {
+    const fn new() -> Self {
+        Self {
+            buf: [0; 128],
+            len: 0,
+        }
+    }
+
+    fn emit(&mut self) {
+        // 截断时换行符没有写入, 使用保留的最后一个字节
+        if self.buf[..self.len].last() != Some(&b'\n') {
+            self.buf[self.len] = b'\n';
+            self.len += 1;
+        }
+        let line = core::str::from_utf8(&self.buf[..self.len]).unwrap_or("@T?\n");
+        TRACER.wait().emit(line);
+    }
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // 按字符截断，保证缓冲区总是合法的 UTF-8；最后一个字节留给 emit 补换行符
+        for c in s.chars() {
+            if self.len + c.len_utf8() > self.buf.len() - 1 {
+                return Err(fmt::Error);
+            }
+            c.encode_utf8(&mut self.buf[self.len..]);
+            self.len += c.len_utf8();
+        }
+        Ok(())
+    }
+}
+
+/// 登记事件类型的名字，解码时用名字代替编号
+pub fn define(kind: u32, name: &str) {
+    if TRACER.get().is_none() || format() == Format::Off {
+        return;
+    }
+    let mut line = Line::new();
+    let _ = writeln!(line, "@TK,{kind},{name}");
+    line.emit();
+}
+
+/// 记录一个事件
+pub fn event(kind: u32, payload: u64) {
+    let format = format();
+    let (Some(tracer), false) = (TRACER.get(), format == Format::Off) else {
+        return;
+    };
+    let record = Record {
+        time_us: tracer.time_us() as u64,
+        tid: tracer.tid().map(clamp_id),
+        cid: tracer.cid().map(clamp_id),
+        kind,
+        payload,
+    };
+    let mut line = Line::new();
+    let _ = match format {
+        Format::Off => unreachable!(),
+        Format::Csv => {
+            let id = |v: Option<u32>| v.map_or(-1, |v| v as i64);
+            writeln!(
+                line,
+                "@TC,{},{},{},{},{}",
+                record.time_us,
+                id(record.tid),
+                id(record.cid),
+                record.kind,
+                record.payload
+            )
+        }
+        Format::Binary => {
+            let _ = line.write_str("@TB");
+            for b in record.encode() {
+                let _ = write!(line, "{b:02x}");
+            }
+            line.write_char('\n')
+        }
+    };
+    line.emit();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let records = [
+            Record {
+                time_us: 1_500_000,
+                tid: Some(3),
+                cid: Some(17),
+                kind: 2,
+                payload: 42,
+            },
+            Record {
+                time_us: 0,
+                tid: None,
+                cid: None,
+                kind: 0,
+                payload: 0,
+            },
+            Record {
+                time_us: u64::MAX,
+                tid: Some(u32::MAX - 1),
+                cid: None,
+                kind: u32::MAX,
+                payload: u64::MAX,
+            },
+        ];
+        for record in records {
+            assert_eq!(Some(record), Record::decode(&record.encode()));
+        }
+    }
+
+    #[test]
+    fn test_encode_layout() {
+        let record = Record {
+            time_us: 0x0102,
+            tid: None,
+            cid: Some(1),
+            kind: 3,
+            payload: 0x0405,
+        };
+        let buf = record.encode();
+        assert_eq!([0x02, 0x01, 0, 0, 0, 0, 0, 0], buf[0..8]);
+        assert_eq!([0xff; 4], buf[8..12]);
+        assert_eq!([1, 0, 0, 0], buf[12..16]);
+        assert_eq!([3, 0, 0, 0], buf[16..20]);
+        assert_eq!([0x05, 0x04, 0, 0, 0, 0, 0, 0], buf[20..28]);
+    }
+
+    #[test]
+    fn test_line_truncate() {
+        // 写满时保留一个字节给换行符
+        let mut line = Line::new();
+        assert!(write!(line, "{:200}", "").is_err());
+        assert_eq!(line.buf.len() - 1, line.len);
+        // 多字节字符不会被截断一半
+        let mut line = Line::new();
+        assert!(write!(line, "{:126}é", "").is_err());
+        assert_eq!(126, line.len);
+    }
+
+    #[test]
+    fn test_decode_none() {
+        // u32::MAX 被截断为 MAX_ID, 不会被当成“没有”
+        let record = Record {
+            time_us: 1,
+            tid: Some(u32::MAX),
+            cid: None,
+            kind: 1,
+            payload: 1,
+        };
+        let decoded = Record::decode(&record.encode()).unwrap();
+        assert_eq!((Some(MAX_ID), None), (decoded.tid, decoded.cid));
+        assert_eq!(MAX_ID, clamp_id(usize::MAX));
+        // 长度不对
+        assert_eq!(None, Record::decode(&[0; Record::SIZE - 1]));
+        assert_eq!(None, Record::decode(&[0; Record::SIZE + 1]));
+    }
+}
@@ -0,0 +1,153 @@
+#![no_std]
+extern crate alloc;
+
+use alloc::{collections::VecDeque, vec, vec::Vec};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+use spin::Once;
+
+pub const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockError {
+    NoDevice,
+    /// 超出设备容量
+    OutOfRange,
+    /// 长度不是 SECTOR_SIZE 的整数倍
+    Unaligned,
+    /// 请求队列已满
+    Busy,
+    Io,
+}
+
+/// 块设备驱动, 每个请求读写一个扇区
+pub trait BlockDevice: Sync {
+    /// 扇区数
+    fn sectors(&self) -> u64;
+    /// 提交请求, 完成前 buf 由驱动持有; 队列满时返回 buf
+    fn submit(&self, sector: u64, buf: Vec<u8>, write: bool) -> Result<usize, Vec<u8>>;
+    /// 取回已完成的请求, 未完成时记录 waker, 完成中断到来时唤醒
+    fn poll(&self, id: usize, waker: &Waker) -> Option<Result<Vec<u8>, BlockError>>;
+    /// 放弃请求, 完成后由驱动释放 buf
+    fn forget(&self, id: usize);
+}
+
+static DEVICE: Once<&'static dyn BlockDevice> = Once::new();
+
+pub fn init(dev: &'static dyn BlockDevice) {
+    DEVICE.call_once(|| dev);
+}
+
+fn device() -> Result<&'static dyn BlockDevice, BlockError> {
+    match DEVICE.get() {
+        Some(dev) if dev.sectors() > 0 => Ok(*dev),
+        _ => Err(BlockError::NoDevice),
+    }
+}
+
+// capacity in sectors, 0 if there is no block device
+pub fn sectors() -> u64 {
+    device().map_or(0, |dev| dev.sectors())
+}
+
+// read buf.len() / SECTOR_SIZE sectors starting at sector
+pub async fn read(sector: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+    let count = check(sector, buf.len())?;
+    let bufs = (0..count).map(|_| vec![0; SECTOR_SIZE]);
+    transfer(sector, bufs, false, |i, data| {
+        buf[i * SECTOR_SIZE..(i + 1) * SECTOR_SIZE].copy_from_slice(&data);
+    })
+    .await
+}
+
+// write buf.len() / SECTOR_SIZE sectors starting at sector
+pub async fn write(sector: u64, buf: &[u8]) -> Result<(), BlockError> {
+    check(sector, buf.len())?;
+    let bufs = buf.chunks(SECTOR_SIZE).map(|chunk| chunk.to_vec());
+    transfer(sector, bufs, true, |_, _| {}).await
+}
+
+fn check(sector: u64, len: usize) -> Result<usize, BlockError> {
+    if len % SECTOR_SIZE != 0 {
+        return Err(BlockError::Unaligned);
+    }
+    let count = len / SECTOR_SIZE;
+    match sector.checked_add(count as u64) {
+        Some(end) if end <= sectors() => Ok(count),
+        _ if sectors() == 0 => Err(BlockError::NoDevice),
+        _ => Err(BlockError::OutOfRange),
+    }
+}
+
+/// 尽量多地提交请求, 队列满时等待最早的请求完成
+async fn transfer(
+    sector: u64,
+    bufs: impl Iterator<Item = Vec<u8>>,
+    write: bool,
+    mut done: impl FnMut(usize, Vec<u8>),
+) -> Result<(), BlockError> {
+    let dev = device()?;
+    let mut pending = VecDeque::new();
+    for (i, mut buf) in bufs.enumerate() {
+        loop {
+            match dev.submit(sector + i as u64, buf, write) {
+                Ok(id) => {
+                    pending.push_back((i, Request::new(id)));
+                    break;
+                }
+                Err(back) => {
+                    let Some((j, req)) = pending.pop_front() else {
+                        return Err(BlockError::Busy);
+                    };
+                    buf = back;
+                    done(j, req.await?);
+                }
+            }
+        }
+    }
+    while let Some((j, req)) = pending.pop_front() {
+        done(j, req.await?);
+    }
+    Ok(())
+}
+
+/// 等待一个已提交的请求, 未完成就被 drop 时放弃该请求
+struct Request {
+    id: usize,
+    finished: bool,
+}
+
+impl Request {
+    fn new(id: usize) -> Self {
+        Self {
+            id,
+            finished: false,
+        }
+    }
+}
+
+impl Future for Request {
+    type Output = Result<Vec<u8>, BlockError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        match DEVICE.wait().poll(this.id, cx.waker()) {
+            Some(ans) => {
+                this.finished = true;
+                Poll::Ready(ans)
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for Request {
+    fn drop(&mut self) {
+        if !self.finished {
+            DEVICE.wait().forget(self.id);
+        }
+    }
+}
@@ -0,0 +1,631 @@
+//! FAT32 磁盘结构: BPB, FAT 表, 目录项
+
+use crate::FsError;
+use alloc::{string::String, vec::Vec};
+use block::SECTOR_SIZE;
+
+pub(crate) const ATTR_DIRECTORY: u8 = 0x10;
+pub(crate) const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_LFN: u8 = 0x0F;
+
+const FAT_MASK: u32 = 0x0FFF_FFFF;
+/// 不小于该值的 FAT 项表示簇链结束
+const FAT_EOC: u32 = 0x0FFF_FFF8;
+
+const DIRENT_SIZE: usize = 32;
+const DIRENT_FREE: u8 = 0xE5;
+/// 1980-01-01, 没有 RTC 时使用的日期
+const FAT_DATE: u16 = (1 << 5) | 1;
+
+/// 短文件名中 base / ext 小写的标志 (NT 扩展)
+const LOWER_BASE: u8 = 0x08;
+const LOWER_EXT: u8 = 0x10;
+
+type Sector = [u8; SECTOR_SIZE];
+
+#[inline]
+fn le16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([buf[off], buf[off + 1]])
+}
+
+#[inline]
+fn le32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+pub(crate) async fn read_sector(sector: u64) -> Result<Sector, FsError> {
+    let mut buf = [0; SECTOR_SIZE];
+    block::read(sector, &mut buf).await?;
+    Ok(buf)
+}
+
+pub(crate) async fn write_sector(sector: u64, buf: &Sector) -> Result<(), FsError> {
+    block::write(sector, buf).await?;
+    Ok(())
+}
+
+/// 目录项
+pub(crate) struct Entry {
+    /// 长文件名, 没有时为短文件名
+    pub name: String,
+    pub short: [u8; 11],
+    pub attr: u8,
+    pub cluster: u32,
+    pub size: u32,
+    /// 短目录项所在的 (扇区, 偏移)
+    pub loc: (u64, usize),
+}
+
+impl Entry {
+    #[inline]
+    pub fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+
+    fn parse(buf: &[u8], loc: (u64, usize)) -> Self {
+        let short: [u8; 11] = buf[..11].try_into().unwrap();
+        let cluster = ((le16(buf, 20) as u32) << 16) | le16(buf, 26) as u32;
+        Self {
+            name: display_short(&short, buf[12]),
+            short,
+            attr: buf[11],
+            cluster,
+            size: le32(buf, 28),
+            loc,
+        }
+    }
+}
+
+/// 8.3 短文件名, 按 NT 标志恢复小写
+fn display_short(short: &[u8; 11], flags: u8) -> String {
+    let part = |bytes: &[u8], lower: bool| -> String {
+        let s = core::str::from_utf8(bytes).unwrap_or("?").trim_end();
+        if lower {
+            s.to_ascii_lowercase()
+        } else {
+            String::from(s)
+        }
+    };
+    let mut name = part(&short[..8], flags & LOWER_BASE != 0);
+    // 0x05 表示首字节实际为 0xE5
+    if short[0] == 0x05 {
+        name.replace_range(..1, "\u{e5}");
+    }
+    let ext = part(&short[8..], flags & LOWER_EXT != 0);
+    if !ext.is_empty() {
+        name.push('.');
+        name.push_str(&ext);
+    }
+    name
+}
+
+/// 将文件名转换为 8.3 短文件名和 NT 小写标志, 不支持需要长文件名的名称
+pub(crate) fn short_name(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, ext) = match name.rfind('.') {
+        Some(i) => (&name[..i], &name[i + 1..]),
+        None => (name, ""),
+    };
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+        return None;
+    }
+    let valid = |c: u8| c.is_ascii_alphanumeric() || b"$%'-_@~`!(){}^#&".contains(&c);
+    if !base.bytes().chain(ext.bytes()).all(valid) {
+        return None;
+    }
+    // 大小写混合时无法用标志表示
+    let case = |s: &str, flag: u8| {
+        let lower = s.bytes().any(|c| c.is_ascii_lowercase());
+        let upper = s.bytes().any(|c| c.is_ascii_uppercase());
+        match (lower, upper) {
+            (true, true) => None,
+            (true, false) => Some(flag),
+            _ => Some(0),
+        }
+    };
+    let flags = case(base, LOWER_BASE)? | case(ext, LOWER_EXT)?;
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.as_bytes());
+    short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    short.make_ascii_uppercase();
+    Some((short, flags))
+}
+
+/// 长文件名目录项对应短文件名的校验和
+fn lfn_checksum(short: &[u8]) -> u8 {
+    short
+        .iter()
+        .fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c))
+}
+
+/// 收集长文件名目录项, 每项 13 个 UTF-16 字符
+#[derive(Default)]
+struct LongName {
+    parts: Vec<[u16; 13]>,
+    checksum: u8,
+}
+
+impl LongName {
+    fn push(&mut self, buf: &[u8]) {
+        let seq = (buf[0] & 0x1F) as usize;
+        if buf[0] & 0x40 != 0 {
+            self.parts = alloc::vec![[0xFFFF; 13]; seq];
+            self.checksum = buf[13];
+        }
+        if seq == 0 || seq > self.parts.len() || buf[13] != self.checksum {
+            self.parts.clear();
+            return;
+        }
+        let part = &mut self.parts[seq - 1];
+        let offsets = (1..11)
+            .step_by(2)
+            .chain((14..26).step_by(2))
+            .chain((28..32).step_by(2));
+        for (c, off) in part.iter_mut().zip(offsets) {
+            *c = le16(buf, off);
+        }
+    }
+
+    fn take(&mut self, short: &[u8; 11]) -> Option<String> {
+        let parts = core::mem::take(&mut self.parts);
+        if parts.is_empty() || lfn_checksum(short) != self.checksum {
+            return None;
+        }
+        let units = parts.iter().flatten().copied().take_while(|&c| c != 0);
+        char::decode_utf16(units)
+            .collect::<Result<String, _>>()
+            .ok()
+    }
+}
+
+/// 已挂载的 FAT32 卷
+pub(crate) struct Volume {
+    sectors_per_cluster: u32,
+    fat_start: u64,
+    fat_sectors: u32,
+    num_fats: u32,
+    data_start: u64,
+    /// 数据簇数量, 簇号从 2 开始
+    clusters: u32,
+    pub root: u32,
+    /// 下一次分配时查找的起点
+    next_free: u32,
+    /// FSInfo 扇区, 第一次修改 FAT 时将其中的空闲计数置为未知
+    fsinfo: Option<u64>,
+}
+
+fn is_fat32(boot: &Sector) -> bool {
+    le16(boot, 11) as usize == SECTOR_SIZE
+        && boot[13].is_power_of_two()
+        && le16(boot, 17) == 0
+        && le16(boot, 22) == 0
+        && le32(boot, 36) != 0
+}
+
+impl Volume {
+    /// 读取 BPB, 支持无分区表的镜像和 MBR 中的第一个 FAT32 分区
+    pub async fn mount() -> Result<Self, FsError> {
+        let mut start = 0;
+        let mut boot = read_sector(0).await?;
+        if boot[510..] != [0x55, 0xAA] {
+            return Err(FsError::Unsupported);
+        }
+        if !is_fat32(&boot) {
+            start = (0..4)
+                .map(|i| &boot[446 + i * 16..446 + (i + 1) * 16])
+                .find(|part| matches!(part[4], 0x0B | 0x0C))
+                .map(|part| le32(part, 8) as u64)
+                .ok_or(FsError::Unsupported)?;
+            boot = read_sector(start).await?;
+            if !is_fat32(&boot) {
+                return Err(FsError::Unsupported);
+            }
+        }
+        Ok(Self::from_boot(&boot, start))
+    }
+
+    /// 从分区起始扇区 start 处的 BPB 计算卷的布局
+    fn from_boot(boot: &Sector, start: u64) -> Self {
+        let sectors_per_cluster = boot[13] as u32;
+        let num_fats = boot[16] as u32;
+        let fat_sectors = le32(boot, 36);
+        let fat_start = start + le16(boot, 14) as u64;
+        let data_start = fat_start + (num_fats * fat_sectors) as u64;
+        let total = le32(boot, 32) as u64;
+        let clusters = total.saturating_sub(data_start - start) / sectors_per_cluster as u64;
+        // FAT 表能描述的簇数可能更少
+        let clusters = clusters.min(fat_sectors as u64 * (SECTOR_SIZE as u64 / 4) - 2) as u32;
+        let fsinfo = match le16(boot, 48) {
+            0 | 0xFFFF => None,
+            n => Some(start + n as u64),
+        };
+        Self {
+            sectors_per_cluster,
+            fat_start,
+            fat_sectors,
+            num_fats,
+            data_start,
+            clusters,
+            root: le32(boot, 44),
+            next_free: 2,
+            fsinfo,
+        }
+    }
+
+    #[inline]
+    pub fn cluster_bytes(&self) -> usize {
+        self.sectors_per_cluster as usize * SECTOR_SIZE
+    }
+
+    #[inline]
+    fn valid(&self, cluster: u32) -> bool {
+        (2..self.clusters + 2).contains(&cluster)
+    }
+
+    #[inline]
+    pub fn cluster_sector(&self, cluster: u32) -> u64 {
+        self.data_start + (cluster - 2) as u64 * self.sectors_per_cluster as u64
+    }
+
+    #[inline]
+    fn fat_pos(&self, cluster: u32) -> (u64, usize) {
+        let off = cluster as usize * 4;
+        (
+            self.fat_start + (off / SECTOR_SIZE) as u64,
+            off % SECTOR_SIZE,
+        )
+    }
+
+    /// 簇链中的下一个簇
+    pub async fn next(&self, cluster: u32) -> Result<Option<u32>, FsError> {
+        if !self.valid(cluster) {
+            return Err(FsError::Corrupt);
+        }
+        let (sector, off) = self.fat_pos(cluster);
+        let next = le32(&read_sector(sector).await?, off) & FAT_MASK;
+        match next {
+            n if n >= FAT_EOC => Ok(None),
+            n if self.valid(n) => Ok(Some(n)),
+            _ => Err(FsError::Corrupt),
+        }
+    }
+
+    /// 修改所有 FAT 副本, 保留高 4 位
+    async fn set(&mut self, cluster: u32, value: u32) -> Result<(), FsError> {
+        if let Some(sector) = self.fsinfo.take() {
+            let mut buf = read_sector(sector).await?;
+            if le32(&buf, 0) == 0x4161_5252 {
+                buf[488..496].fill(0xFF);
+                write_sector(sector, &buf).await?;
+            }
+        }
+        let (sector, off) = self.fat_pos(cluster);
+        for i in 0..self.num_fats {
+            let sector = sector + (i * self.fat_sectors) as u64;
+            let mut buf = read_sector(sector).await?;
+            let old = le32(&buf, off);
+            let new = (old & !FAT_MASK) | (value & FAT_MASK);
+            buf[off..off + 4].copy_from_slice(&new.to_le_bytes());
+            write_sector(sector, &buf).await?;
+        }
+        Ok(())
+    }
+
+    /// 分配一个簇并接到 prev 之后, 目录簇需要清零
+    pub async fn alloc(&mut self, prev: Option<u32>, zero: bool) -> Result<u32, FsError> {
+        let mut cached: Option<(u64, Sector)> = None;
+        let mut found = None;
+        for n in 0..self.clusters {
+            let cluster = 2 + (self.next_free - 2 + n) % self.clusters;
+            let (sector, off) = self.fat_pos(cluster);
+            let buf = match cached {
+                Some((s, ref buf)) if s == sector => buf,
+                _ => &cached.insert((sector, read_sector(sector).await?)).1,
+            };
+            if le32(buf, off) & FAT_MASK == 0 {
+                found = Some(cluster);
+                break;
+            }
+        }
+        let cluster = found.ok_or(FsError::NoSpace)?;
+        self.set(cluster, FAT_MASK).await?;
+        if let Some(prev) = prev {
+            self.set(prev, cluster).await?;
+        }
+        if zero {
+            let start = self.cluster_sector(cluster);
+            for sector in start..start + self.sectors_per_cluster as u64 {
+                write_sector(sector, &[0; SECTOR_SIZE]).await?;
+            }
+        }
+        self.next_free = cluster + 1;
+        if !self.valid(self.next_free) {
+            self.next_free = 2;
+        }
+        Ok(cluster)
+    }
+
+    /// 释放整条簇链
+    pub async fn free(&mut self, mut cluster: u32) -> Result<(), FsError> {
+        loop {
+            let next = self.next(cluster).await?;
+            self.set(cluster, 0).await?;
+            match next {
+                Some(n) => cluster = n,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// 目录占用的所有扇区
+    async fn dir_sectors(&self, cluster: u32) -> Result<Vec<u64>, FsError> {
+        let mut sectors = Vec::new();
+        let mut cluster = Some(cluster);
+        while let Some(c) = cluster {
+            let start = self.cluster_sector(c);
+            sectors.extend(start..start + self.sectors_per_cluster as u64);
+            cluster = self.next(c).await?;
+            if sectors.len() > self.clusters as usize * self.sectors_per_cluster as usize {
+                return Err(FsError::Corrupt);
+            }
+        }
+        Ok(sectors)
+    }
+
+    /// 读取目录中的所有有效项, 不包括 "." 和 ".."
+    pub async fn read_dir(&self, cluster: u32) -> Result<Vec<Entry>, FsError> {
+        let mut entries = Vec::new();
+        let mut lfn = LongName::default();
+        for sector in self.dir_sectors(cluster).await? {
+            let buf = read_sector(sector).await?;
+            for off in (0..SECTOR_SIZE).step_by(DIRENT_SIZE) {
+                let raw = &buf[off..off + DIRENT_SIZE];
+                match raw[0] {
+                    0 => return Ok(entries),
+                    DIRENT_FREE => continue,
+                    _ => {}
+                }
+                if raw[11] == ATTR_LFN {
+                    lfn.push(raw);
+                    continue;
+                }
+                let mut entry = Entry::parse(raw, (sector, off));
+                if let Some(name) = lfn.take(&entry.short) {
+                    entry.name = name;
+                }
+                if entry.attr & ATTR_VOLUME_ID == 0 && raw[0] != b'.' {
+                    entries.push(entry);
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// 按名称查找, 不区分大小写
+    pub async fn lookup(&self, dir: u32, name: &str) -> Result<Option<Entry>, FsError> {
+        let short = short_name(name).map(|(short, _)| short);
+        Ok(self
+            .read_dir(dir)
+            .await?
+            .into_iter()
+            .find(|e| e.name.eq_ignore_ascii_case(name) || Some(e.short) == short))
+    }
+
+    /// 在目录中创建短文件名目录项, 没有空位时扩展目录
+    pub async fn create(
+        &mut self,
+        dir: u32,
+        name: &str,
+        attr: u8,
+        cluster: u32,
+    ) -> Result<Entry, FsError> {
+        let (short, flags) = short_name(name).ok_or(FsError::InvalidName)?;
+        let mut slot = None;
+        let sectors = self.dir_sectors(dir).await?;
+        'search: for &sector in sectors.iter() {
+            let buf = read_sector(sector).await?;
+            for off in (0..SECTOR_SIZE).step_by(DIRENT_SIZE) {
+                if matches!(buf[off], 0 | DIRENT_FREE) {
+                    slot = Some((sector, off));
+                    break 'search;
+                }
+            }
+        }
+        let loc = match slot {
+            Some(loc) => loc,
+            None => {
+                let last = *sectors.last().unwrap();
+                let last = ((last - self.data_start) / self.sectors_per_cluster as u64) as u32 + 2;
+                let new = self.alloc(Some(last), true).await?;
+                (self.cluster_sector(new), 0)
+            }
+        };
+        let mut buf = read_sector(loc.0).await?;
+        let raw = &mut buf[loc.1..loc.1 + DIRENT_SIZE];
+        raw.fill(0);
+        raw[..11].copy_from_slice(&short);
+        raw[11] = attr;
+        raw[12] = flags;
+        for off in [16, 18, 24] {
+            raw[off..off + 2].copy_from_slice(&FAT_DATE.to_le_bytes());
+        }
+        let mut entry = Entry::parse(raw, loc);
+        write_sector(loc.0, &buf).await?;
+        entry.name = String::from(name);
+        self.update(&entry, cluster, 0).await?;
+        entry.cluster = cluster;
+        Ok(entry)
+    }
+
+    /// 更新目录项中的首簇号和文件大小
+    pub async fn update(&self, entry: &Entry, cluster: u32, size: u32) -> Result<(), FsError> {
+        let (sector, off) = entry.loc;
+        let mut buf = read_sector(sector).await?;
+        let raw = &mut buf[off..off + DIRENT_SIZE];
+        raw[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+        raw[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+        raw[28..32].copy_from_slice(&size.to_le_bytes());
+        write_sector(sector, &buf).await
+    }
+
+    /// 新目录的 "." 和 ".." 项, 父目录为根目录时 ".." 的簇号为 0
+    pub async fn init_dir(&self, cluster: u32, parent: u32) -> Result<(), FsError> {
+        let parent = if parent == self.root { 0 } else { parent };
+        let mut buf = [0; SECTOR_SIZE];
+        for (i, (name, c)) in [(".", cluster), ("..", parent)].into_iter().enumerate() {
+            let raw = &mut buf[i * DIRENT_SIZE..(i + 1) * DIRENT_SIZE];
+            raw[..11].fill(b' ');
+            raw[..name.len()].copy_from_slice(name.as_bytes());
+            raw[11] = ATTR_DIRECTORY;
+            for off in [16, 18, 24] {
+                raw[off..off + 2].copy_from_slice(&FAT_DATE.to_le_bytes());
+            }
+            raw[20..22].copy_from_slice(&((c >> 16) as u16).to_le_bytes());
+            raw[26..28].copy_from_slice(&(c as u16).to_le_bytes());
+        }
+        write_sector(self.cluster_sector(cluster), &buf).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_name() {
+        assert_eq!(Some((*b"README  TXT", 0)), short_name("README.TXT"));
+        assert_eq!(
+            Some((*b"BENCH   CSV", LOWER_BASE | LOWER_EXT)),
+            short_name("bench.csv")
+        );
+        assert_eq!(Some((*b"MAKEFILE   ", LOWER_BASE)), short_name("makefile"));
+        assert_eq!(Some((*b"A       B  ", LOWER_EXT)), short_name("A.b"));
+        // 大小写混合、过长、非法字符都需要长文件名
+        assert_eq!(None, short_name("ReadMe.txt"));
+        assert_eq!(None, short_name("longfilename.txt"));
+        assert_eq!(None, short_name("a.text"));
+        assert_eq!(None, short_name(".hidden"));
+        assert_eq!(None, short_name("a b.txt"));
+        assert_eq!(None, short_name("a.b.c"));
+    }
+
+    #[test]
+    fn test_display_short() {
+        assert_eq!("README.TXT", display_short(b"README  TXT", 0));
+        assert_eq!(
+            "bench.csv",
+            display_short(b"BENCH   CSV", LOWER_BASE | LOWER_EXT)
+        );
+        assert_eq!("makefile", display_short(b"MAKEFILE   ", LOWER_BASE));
+        assert_eq!("\u{e5}BC", display_short(b"\x05BC        ", 0));
+    }
+
+    #[test]
+    fn test_lfn_checksum() {
+        assert_eq!(0x41, lfn_checksum(&[0x80, 0x01]));
+        // 规范中的写法
+        let short = b"BENCH   CSV";
+        let mut sum = 0u8;
+        for &c in short {
+            sum = ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(c);
+        }
+        assert_eq!(sum, lfn_checksum(short));
+    }
+
+    /// 第 seq 个长文件名目录项, 包含 name 中对应的 13 个字符
+    fn lfn_entry(name: &[u16], seq: usize, checksum: u8) -> [u8; DIRENT_SIZE] {
+        let mut raw = [0u8; DIRENT_SIZE];
+        let last = name.len().div_ceil(13);
+        raw[0] = seq as u8 | if seq == last { 0x40 } else { 0 };
+        raw[11] = ATTR_LFN;
+        raw[13] = checksum;
+        let offsets = (1..11)
+            .step_by(2)
+            .chain((14..26).step_by(2))
+            .chain((28..32).step_by(2));
+        for (i, off) in offsets.enumerate() {
+            // 名字之后是一个 0, 然后用 0xFFFF 填充
+            let c = match (seq - 1) * 13 + i {
+                j if j < name.len() => name[j],
+                j if j == name.len() => 0,
+                _ => 0xFFFF,
+            };
+            raw[off..off + 2].copy_from_slice(&c.to_le_bytes());
+        }
+        raw
+    }
+
+    #[test]
+    fn test_long_name() {
+        let short = *b"ALONGF~1TXT";
+        let name: Vec<u16> = "a_long_file_name.txt".encode_utf16().collect();
+        let checksum = lfn_checksum(&short);
+
+        // 磁盘上从最后一项开始倒序存放
+        let mut lfn = LongName::default();
+        lfn.push(&lfn_entry(&name, 2, checksum));
+        lfn.push(&lfn_entry(&name, 1, checksum));
+        assert_eq!(Some(String::from("a_long_file_name.txt")), lfn.take(&short));
+        // 取出后清空
+        assert_eq!(None, lfn.take(&short));
+
+        // 短文件名被其他系统改过, 校验和不再匹配
+        lfn.push(&lfn_entry(&name, 2, checksum));
+        lfn.push(&lfn_entry(&name, 1, checksum));
+        assert_eq!(None, lfn.take(b"OTHER   TXT"));
+
+        // 中间一项的校验和不一致, 整个长文件名作废
+        lfn.push(&lfn_entry(&name, 2, checksum));
+        lfn.push(&lfn_entry(&name, 1, checksum.wrapping_add(1)));
+        assert_eq!(None, lfn.take(&short));
+    }
+
+    /// 每簇 8 扇区, 保留 32 扇区, 2 个 FAT 各 fat_sectors 扇区, 共 65536 扇区
+    fn boot_sector(fat_sectors: u32) -> Sector {
+        let mut boot = [0u8; SECTOR_SIZE];
+        boot[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+        boot[13] = 8;
+        boot[14..16].copy_from_slice(&32u16.to_le_bytes());
+        boot[16] = 2;
+        boot[32..36].copy_from_slice(&65536u32.to_le_bytes());
+        boot[36..40].copy_from_slice(&fat_sectors.to_le_bytes());
+        boot[44..48].copy_from_slice(&2u32.to_le_bytes());
+        boot[48..50].copy_from_slice(&1u16.to_le_bytes());
+        boot[510] = 0x55;
+        boot[511] = 0xAA;
+        boot
+    }
+
+    #[test]
+    fn test_volume_layout() {
+        let boot = boot_sector(64);
+        assert!(is_fat32(&boot));
+        let vol = Volume::from_boot(&boot, 2048);
+        assert_eq!(2080, vol.fat_start);
+        assert_eq!(2208, vol.data_start);
+        assert_eq!((65536 - 160) / 8, vol.clusters);
+        assert_eq!(2, vol.root);
+        assert_eq!(Some(2049), vol.fsinfo);
+        assert_eq!(4096, vol.cluster_bytes());
+
+        assert_eq!(2208, vol.cluster_sector(2));
+        assert_eq!(2232, vol.cluster_sector(5));
+        assert_eq!((2080, 8), vol.fat_pos(2));
+        assert_eq!((2080, 508), vol.fat_pos(127));
+        assert_eq!((2081, 0), vol.fat_pos(128));
+
+        assert!(!vol.valid(0));
+        assert!(!vol.valid(1));
+        assert!(vol.valid(2));
+        assert!(vol.valid(vol.clusters + 1));
+        assert!(!vol.valid(vol.clusters + 2));
+    }
+
+    #[test]
+    fn test_volume_layout_small_fat() {
+        // FAT 表只能描述 8 * 128 - 2 个簇
+        let vol = Volume::from_boot(&boot_sector(8), 0);
+        assert_eq!(48, vol.data_start);
+        assert_eq!(8 * 128 - 2, vol.clusters);
+    }
+}
@@ -0,0 +1,354 @@
+#![no_std]
+extern crate alloc;
+
+mod fat;
+
+use alloc::{string::String, vec, vec::Vec};
+use block::{BlockError, SECTOR_SIZE};
+use core::{
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use executor::async_yield;
+use fat::{read_sector, write_sector, Entry, Volume, ATTR_ARCHIVE, ATTR_DIRECTORY};
+use spin::Mutex;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FsError {
+    NoDevice,
+    Io(BlockError),
+    /// 不是 FAT32 卷
+    Unsupported,
+    /// 簇链或目录项损坏
+    Corrupt,
+    NotFound,
+    NotDir,
+    IsDir,
+    Exists,
+    /// 只支持 8.3 短文件名
+    InvalidName,
+    NoSpace,
+    ReadOnly,
+}
+
+impl From<BlockError> for FsError {
+    fn from(e: BlockError) -> Self {
+        match e {
+            BlockError::NoDevice => FsError::NoDevice,
+            e => FsError::Io(e),
+        }
+    }
+}
+
+/// readdir 返回的目录项
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: usize,
+}
+
+// 已挂载的卷; 操作期间由持锁者取出, 不能跨 await 持有 spin 锁
+static VOLUME: Mutex<Option<Volume>> = Mutex::new(None);
+static BUSY: AtomicBool = AtomicBool::new(false);
+
+/// 独占访问卷, drop 时放回
+struct FsGuard(Option<Volume>);
+
+impl Deref for FsGuard {
+    type Target = Volume;
+
+    fn deref(&self) -> &Volume {
+        self.0.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for FsGuard {
+    fn deref_mut(&mut self) -> &mut Volume {
+        self.0.as_mut().unwrap()
+    }
+}
+
+impl Drop for FsGuard {
+    fn drop(&mut self) {
+        *VOLUME.lock() = self.0.take();
+        BUSY.store(false, Ordering::Release);
+    }
+}
+
+/// 获取卷, 第一次使用时挂载
+async fn volume() -> Result<FsGuard, FsError> {
+    while BUSY.swap(true, Ordering::Acquire) {
+        async_yield().await;
+    }
+    let mut guard = FsGuard(VOLUME.lock().take());
+    if guard.0.is_none() {
+        guard.0 = Some(Volume::mount().await?);
+    }
+    Ok(guard)
+}
+
+// mount the FAT32 volume on the block device, other calls mount it lazily
+pub async fn mount() -> Result<(), FsError> {
+    volume().await.map(drop)
+}
+
+/// 路径解析结果
+enum Node {
+    Root,
+    Entry(Entry),
+}
+
+impl Node {
+    fn dir_cluster(&self, vol: &Volume) -> Result<u32, FsError> {
+        match self {
+            Node::Root => Ok(vol.root),
+            Node::Entry(e) if e.is_dir() => Ok(e.cluster),
+            Node::Entry(_) => Err(FsError::NotDir),
+        }
+    }
+}
+
+/// 路径分量, 以 "/" 分隔, 忽略 "." 和空分量
+fn components(path: &str) -> Result<Vec<&str>, FsError> {
+    let parts: Vec<&str> = path
+        .split('/')
+        .filter(|p| !p.is_empty() && *p != ".")
+        .collect();
+    if parts.contains(&"..") {
+        return Err(FsError::InvalidName);
+    }
+    Ok(parts)
+}
+
+async fn resolve(vol: &Volume, parts: &[&str]) -> Result<Node, FsError> {
+    let mut node = Node::Root;
+    for part in parts {
+        let dir = node.dir_cluster(vol)?;
+        node = Node::Entry(vol.lookup(dir, part).await?.ok_or(FsError::NotFound)?);
+    }
+    Ok(node)
+}
+
+/// 解析父目录, 返回 (目录簇号, 文件名)
+async fn resolve_parent<'a>(vol: &Volume, path: &'a str) -> Result<(u32, &'a str), FsError> {
+    let parts = components(path)?;
+    let (name, parent) = parts.split_last().ok_or(FsError::InvalidName)?;
+    let dir = resolve(vol, parent).await?.dir_cluster(vol)?;
+    Ok((dir, name))
+}
+
+// list a directory, "." and ".." are not included
+pub async fn readdir(path: &str) -> Result<Vec<DirEntry>, FsError> {
+    let vol = volume().await?;
+    let dir = resolve(&vol, &components(path)?).await?.dir_cluster(&vol)?;
+    Ok(vol
+        .read_dir(dir)
+        .await?
+        .into_iter()
+        .map(|e| DirEntry {
+            is_dir: e.is_dir(),
+            size: e.size as usize,
+            name: e.name,
+        })
+        .collect())
+}
+
+// create a directory, the parent must exist
+pub async fn mkdir(path: &str) -> Result<(), FsError> {
+    let mut vol = volume().await?;
+    let (dir, name) = resolve_parent(&vol, path).await?;
+    if vol.lookup(dir, name).await?.is_some() {
+        return Err(FsError::Exists);
+    }
+    let cluster = vol.alloc(None, true).await?;
+    vol.init_dir(cluster, dir).await?;
+    vol.create(dir, name, ATTR_DIRECTORY, cluster).await?;
+    Ok(())
+}
+
+// open an existing file for reading
+pub async fn open(path: &str) -> Result<File, FsError> {
+    let vol = volume().await?;
+    match resolve(&vol, &components(path)?).await? {
+        Node::Entry(e) if !e.is_dir() => Ok(File::new(e, false)),
+        _ => Err(FsError::IsDir),
+    }
+}
+
+// create a file for writing, truncating it if it exists
+pub async fn create(path: &str) -> Result<File, FsError> {
+    // 查找、创建和截断在同一次持有卷时完成, 中间不会插入其他操作
+    let mut vol = volume().await?;
+    let mut file = open_write(&mut vol, path).await?;
+    // 大小为 0 的文件也可能已经分配了簇
+    if file.entry.cluster != 0 || file.size > 0 {
+        if file.entry.cluster != 0 {
+            vol.free(file.entry.cluster).await?;
+        }
+        vol.update(&file.entry, 0, 0).await?;
+        file.entry.cluster = 0;
+        file.size = 0;
+    }
+    Ok(file)
+}
+
+// open a file for writing at its end, creating it if it does not exist
+pub async fn append(path: &str) -> Result<File, FsError> {
+    let mut vol = volume().await?;
+    let mut file = open_write(&mut vol, path).await?;
+    file.pos = file.size;
+    Ok(file)
+}
+
+async fn open_write(vol: &mut Volume, path: &str) -> Result<File, FsError> {
+    let (dir, name) = resolve_parent(vol, path).await?;
+    let entry = match vol.lookup(dir, name).await? {
+        Some(e) if e.is_dir() => return Err(FsError::IsDir),
+        Some(e) => e,
+        None => vol.create(dir, name, ATTR_ARCHIVE, 0).await?,
+    };
+    Ok(File::new(entry, true))
+}
+
+/// 打开的文件, 写入时立即更新目录项
+pub struct File {
+    entry: Entry,
+    size: usize,
+    pos: usize,
+    writable: bool,
+    /// 最近访问的 (簇序号, 簇号), 顺序读写时避免从头遍历簇链
+    cursor: Option<(usize, u32)>,
+}
+
+impl File {
+    fn new(entry: Entry, writable: bool) -> Self {
+        Self {
+            size: entry.size as usize,
+            entry,
+            pos: 0,
+            writable,
+            cursor: None,
+        }
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    #[inline]
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    // move the cursor, clamped to the file size
+    pub fn seek(&mut self, pos: usize) {
+        self.pos = pos.min(self.size);
+    }
+
+    /// 第 index 个簇, alloc 时在簇链末尾补充
+    async fn cluster_at(
+        &mut self,
+        vol: &mut Volume,
+        index: usize,
+        alloc: bool,
+    ) -> Result<Option<u32>, FsError> {
+        if self.entry.cluster == 0 {
+            if !alloc {
+                return Ok(None);
+            }
+            self.entry.cluster = vol.alloc(None, false).await?;
+        }
+        let (mut i, mut cluster) = match self.cursor {
+            Some((i, c)) if i <= index => (i, c),
+            _ => (0, self.entry.cluster),
+        };
+        while i < index {
+            cluster = match vol.next(cluster).await? {
+                Some(next) => next,
+                None if alloc => vol.alloc(Some(cluster), false).await?,
+                None => return Ok(None),
+            };
+            i += 1;
+        }
+        self.cursor = Some((i, cluster));
+        Ok(Some(cluster))
+    }
+
+    /// 当前位置所在的 (扇区, 扇区内偏移)
+    async fn locate(&mut self, vol: &mut Volume, alloc: bool) -> Result<(u64, usize), FsError> {
+        let cluster_bytes = vol.cluster_bytes();
+        let cluster = self
+            .cluster_at(vol, self.pos / cluster_bytes, alloc)
+            .await?
+            .ok_or(FsError::Corrupt)?;
+        let off = self.pos % cluster_bytes;
+        Ok((
+            vol.cluster_sector(cluster) + (off / SECTOR_SIZE) as u64,
+            off % SECTOR_SIZE,
+        ))
+    }
+
+    // read from the cursor, returns 0 at the end of file
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        let mut vol = volume().await?;
+        let len = buf.len().min(self.size - self.pos);
+        let mut done = 0;
+        while done < len {
+            let (sector, off) = self.locate(&mut vol, false).await?;
+            let n = (SECTOR_SIZE - off).min(len - done);
+            let data = read_sector(sector).await?;
+            buf[done..done + n].copy_from_slice(&data[off..off + n]);
+            done += n;
+            self.pos += n;
+        }
+        Ok(done)
+    }
+
+    // read the rest of the file
+    pub async fn read_to_end(&mut self) -> Result<Vec<u8>, FsError> {
+        let mut buf = vec![0; self.size - self.pos];
+        let n = self.read(&mut buf).await?;
+        buf.truncate(n);
+        Ok(buf)
+    }
+
+    // write at the cursor, growing the file as needed
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize, FsError> {
+        if !self.writable {
+            return Err(FsError::ReadOnly);
+        }
+        // FAT32 文件大小不超过 4GB
+        if self.pos + buf.len() > u32::MAX as usize {
+            return Err(FsError::NoSpace);
+        }
+        let mut vol = volume().await?;
+        let first = self.entry.cluster;
+        let mut done = 0;
+        let ans = async {
+            while done < buf.len() {
+                let (sector, off) = self.locate(&mut vol, true).await?;
+                let n = (SECTOR_SIZE - off).min(buf.len() - done);
+                let mut data = if n == SECTOR_SIZE {
+                    [0; SECTOR_SIZE]
+                } else {
+                    read_sector(sector).await?
+                };
+                data[off..off + n].copy_from_slice(&buf[done..done + n]);
+                write_sector(sector, &data).await?;
+                done += n;
+                self.pos += n;
+            }
+            Ok(())
+        }
+        .await;
+        // 即使中途失败, 已写入的部分也记录到目录项
+        if self.pos > self.size || self.entry.cluster != first {
+            self.size = self.size.max(self.pos);
+            vol.update(&self.entry, self.entry.cluster, self.size as u32)
+                .await?;
+        }
+        ans.map(|_| done)
+    }
+}
@@ -0,0 +1,66 @@
+#![no_std]
+extern crate alloc;
+
+use alloc::vec::Vec;
+use spin::Once;
+
+/// 分配记录保存的返回地址层数
+pub const SITE_DEPTH: usize = 4;
+
+/// 堆使用统计, 单位为字节
+#[derive(Clone, Copy, Debug)]
+pub struct HeapStats {
+    pub total: usize,
+    pub in_use: usize,
+    pub peak: usize,
+    pub allocs: usize,
+    pub deallocs: usize,
+    /// 分配失败次数
+    pub failures: usize,
+    /// 最大可分配块
+    pub largest_free: usize,
+}
+
+/// 未释放的分配
+#[derive(Clone, Copy, Debug)]
+pub struct AllocRecord {
+    pub ptr: usize,
+    pub size: usize,
+    /// 分配时所在线程, None 表示调度器或启动阶段
+    pub tid: Option<usize>,
+    /// 调用点返回地址, 可用 addr2line 定位
+    pub sites: [usize; SITE_DEPTH],
+}
+
+pub trait Memory: Sync {
+    fn heap_stats(&self) -> HeapStats;
+    fn track(&self, enable: bool);
+    fn records(&self) -> Vec<AllocRecord>;
+    fn dump(&self);
+}
+
+static MEMORY: Once<&'static dyn Memory> = Once::new();
+
+pub fn init(mem: &'static dyn Memory) {
+    MEMORY.call_once(|| mem);
+}
+
+// heap usage, largest_free is probed and relatively slow
+pub fn heap_stats() -> HeapStats {
+    MEMORY.wait().heap_stats()
+}
+
+// start or stop recording allocation sites, starting clears old records
+pub fn track(enable: bool) {
+    MEMORY.wait().track(enable);
+}
+
+// live allocations recorded since track(true)
+pub fn records() -> Vec<AllocRecord> {
+    MEMORY.wait().records()
+}
+
+// print heap usage and live allocations to the console
+pub fn dump() {
+    MEMORY.wait().dump();
+}
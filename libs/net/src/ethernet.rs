@@ -1,10 +1,13 @@
 extern crate alloc;
 
-use alloc::{collections::BTreeMap, vec};
+use alloc::{collections::BTreeMap, vec, vec::Vec};
 use smoltcp::{
     iface::{Route, Routes},
     phy::{self, Device, DeviceCapabilities, Medium},
-    socket::{Dhcpv4Event, Dhcpv4Socket, TcpSocketBuffer},
+    socket::{
+        Dhcpv4Event, Dhcpv4Socket, IcmpEndpoint, IcmpPacketMetadata, IcmpSocketBuffer,
+        TcpSocketBuffer,
+    },
     wire::{IpCidr, Ipv4Address, Ipv4Cidr},
     Result,
 };
@@ -15,6 +18,7 @@ use stdio::log::info;
 use var_bitmap::Bitmap;
 
 pub type TcpSocket = smoltcp::socket::TcpSocket<'static>;
+pub type IcmpSocket = smoltcp::socket::IcmpSocket<'static>;
 pub type Interface<T> = smoltcp::iface::Interface<'static, T>;
 pub type InterfaceInner = smoltcp::iface::Context<'static>;
 pub use smoltcp::{
@@ -145,6 +149,12 @@ pub struct EthernetDriver {
     ethernet: Interface<NetDevice>,
     // /// Internal dhcp socket
     dhcp: SocketHandle,
+    /// MAC address of the interface
+    macaddr: [u8; 6],
+    /// Default gateway from dhcp
+    gateway: Option<Ipv4Address>,
+    /// Handles of all tcp sockets, for netstat
+    tcp: Vec<SocketHandle>,
 }
 
 impl EthernetDriver {
@@ -158,6 +168,9 @@ impl EthernetDriver {
             port_map: Bitmap::with_size(PORTS_NUM),
             ethernet,
             dhcp,
+            macaddr: *macaddr,
+            gateway: None,
+            tcp: Vec::new(),
         }
     }
 
@@ -179,6 +192,7 @@ impl EthernetDriver {
         match dhcp.poll() {
             Some(Dhcpv4Event::Configured(config)) => {
                 self.set_ipv4_addr(config.address);
+                self.gateway = config.router;
                 if let Some(router) = config.router {
                     self.ethernet
                         .routes_mut()
@@ -190,6 +204,7 @@ impl EthernetDriver {
             }
             Some(Dhcpv4Event::Deconfigured) => {
                 self.set_ipv4_addr(Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0));
+                self.gateway = None;
                 self.ethernet.routes_mut().remove_default_ipv4_route();
             }
             _ => {}
@@ -253,13 +268,51 @@ impl EthernetDriver {
         let rx_buffer = TcpSocketBuffer::new(vec![0; 16384]);
         let tx_buffer = TcpSocketBuffer::new(vec![0; 16384]);
         let tcp_socket = TcpSocket::new(rx_buffer, tx_buffer);
-        self.ethernet.add_socket(tcp_socket)
+        let handle = self.ethernet.add_socket(tcp_socket);
+        self.tcp.push(handle);
+        handle
     }
 
     /// Releases a socket from the internal socket set.
     pub fn release(&mut self, handle: SocketHandle) {
+        self.tcp.retain(|h| *h != handle);
         self.ethernet.remove_socket(handle);
     }
+
+    /// Creates a new ICMP socket bound to `ident`.
+    pub fn add_icmp_socket(&mut self, ident: u16) -> Result<SocketHandle> {
+        let rx_buffer = IcmpSocketBuffer::new(vec![IcmpPacketMetadata::EMPTY; 4], vec![0; 1024]);
+        let tx_buffer = IcmpSocketBuffer::new(vec![IcmpPacketMetadata::EMPTY; 4], vec![0; 1024]);
+        let mut icmp_socket = IcmpSocket::new(rx_buffer, tx_buffer);
+        icmp_socket.bind(IcmpEndpoint::Ident(ident))?;
+        Ok(self.ethernet.add_socket(icmp_socket))
+    }
+
+    /// Finds an ICMP socket with a `SocketHandle`.
+    pub fn get_icmp_socket(&mut self, handle: SocketHandle) -> &mut IcmpSocket {
+        self.ethernet.get_socket::<IcmpSocket>(handle)
+    }
+
+    /// Handles of all tcp sockets.
+    pub fn tcp_sockets(&self) -> &[SocketHandle] {
+        &self.tcp
+    }
+
+    pub fn macaddr(&self) -> [u8; 6] {
+        self.macaddr
+    }
+
+    /// The first ipv4 address, unspecified before dhcp finishes.
+    pub fn ipv4_cidr(&self) -> Option<Ipv4Cidr> {
+        self.ethernet.ip_addrs().iter().find_map(|addr| match addr {
+            IpCidr::Ipv4(cidr) if !cidr.address().is_unspecified() => Some(*cidr),
+            _ => None,
+        })
+    }
+
+    pub fn gateway(&self) -> Option<Ipv4Address> {
+        self.gateway
+    }
 }
 
 /// A thread-safe wrapper for `EthernetDriver`.
@@ -5,12 +5,16 @@ mod net_io;
 mod socket;
 
 extern crate alloc;
-use alloc::{borrow::ToOwned, fmt, format, string::String};
+use alloc::{borrow::ToOwned, fmt, format, string::String, vec::Vec};
 use ethernet::GlobalEthernetDriver;
 pub use ethernet::{Duration, Instant, SocketHandle};
+use smoltcp::{
+    phy::ChecksumCapabilities,
+    wire::{Icmpv4Packet, Icmpv4Repr},
+};
 pub use smoltcp::{
     socket::TcpState,
-    wire::{IpAddress, IpEndpoint},
+    wire::{IpAddress, IpEndpoint, Ipv4Address, Ipv4Cidr},
     Error,
 };
 pub use socket::TcpListener;
@@ -59,6 +63,50 @@ impl fmt::Debug for SocketState {
     }
 }
 
+/// 网卡配置，给 ifconfig 用
+#[derive(Clone, Copy, Debug)]
+pub struct IfInfo {
+    pub mac: [u8; 6],
+    /// dhcp 完成前为 None
+    pub cidr: Option<Ipv4Cidr>,
+    pub gateway: Option<Ipv4Address>,
+}
+
+/// 一个 tcp 套接字的概况，给 netstat 用
+#[derive(Clone, Copy, Debug)]
+pub struct SocketInfo {
+    pub handle: SocketHandle,
+    pub local: IpEndpoint,
+    pub remote: IpEndpoint,
+    pub state: TcpState,
+}
+
+pub fn sys_if_info() -> IfInfo {
+    ETHERNET.critical(|driver| IfInfo {
+        mac: driver.macaddr(),
+        cidr: driver.ipv4_cidr(),
+        gateway: driver.gateway(),
+    })
+}
+
+pub fn sys_sock_list() -> Vec<SocketInfo> {
+    ETHERNET.critical(|driver| {
+        let handles = driver.tcp_sockets().to_vec();
+        handles
+            .into_iter()
+            .map(|handle| {
+                let socket = driver.get_socket(handle);
+                SocketInfo {
+                    handle,
+                    local: socket.local_endpoint(),
+                    remote: socket.remote_endpoint(),
+                    state: socket.state(),
+                }
+            })
+            .collect()
+    })
+}
+
 pub fn sys_sock_create() -> SocketHandle {
     ETHERNET.add_socket()
 }
@@ -110,6 +158,61 @@ pub fn sys_sock_release(sock: SocketHandle) {
     ETHERNET.release_socket(sock);
 }
 
+/// 创建一个 ICMP 套接字，只接收标识为 `ident` 的 echo 回复
+pub fn sys_icmp_create(ident: u16) -> Result<SocketHandle> {
+    ETHERNET.critical(|driver| driver.add_icmp_socket(ident))
+}
+
+/// 发送一个 echo 请求
+pub fn sys_ping_send(
+    sock: SocketHandle,
+    ident: u16,
+    addr: Ipv4Address,
+    seq_no: u16,
+    data: &[u8],
+) -> Result<()> {
+    ETHERNET.critical(|driver| {
+        let socket = driver.get_icmp_socket(sock);
+        if !socket.can_send() {
+            return Err(Error::Exhausted);
+        }
+        let repr = Icmpv4Repr::EchoRequest {
+            ident,
+            seq_no,
+            data,
+        };
+        let buf = socket.send(repr.buffer_len(), addr.into())?;
+        let mut packet = Icmpv4Packet::new_unchecked(buf);
+        repr.emit(&mut packet, &ChecksumCapabilities::default());
+        Ok(())
+    })
+}
+
+/// 取出一个 echo 回复，返回来源和序号；没有回复时返回 None
+pub fn sys_ping_recv(sock: SocketHandle, ident: u16) -> Result<Option<(IpAddress, u16)>> {
+    ETHERNET.critical(|driver| {
+        let socket = driver.get_icmp_socket(sock);
+        while socket.can_recv() {
+            let (payload, from) = socket.recv()?;
+            let packet = Icmpv4Packet::new_checked(payload)?;
+            let repr = Icmpv4Repr::parse(&packet, &ChecksumCapabilities::default())?;
+            if let Icmpv4Repr::EchoReply {
+                ident: id, seq_no, ..
+            } = repr
+            {
+                if id == ident {
+                    return Ok(Some((from, seq_no)));
+                }
+            }
+        }
+        Ok(None)
+    })
+}
+
+pub fn sys_icmp_release(sock: SocketHandle) {
+    ETHERNET.critical(|driver| driver.release(sock));
+}
+
 use core::task::Context;
 /// async version
 pub use net_io::*;
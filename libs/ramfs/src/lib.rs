@@ -0,0 +1,343 @@
+#![no_std]
+//! 只读内存文件系统, 内容来自链接进内核镜像的 cpio (newc) initrd
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use spin::Once;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RamfsError {
+    /// 没有 initrd
+    NotMounted,
+    /// initrd 格式错误
+    Corrupt,
+    NotFound,
+    NotDir,
+    IsDir,
+}
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+const NEWC_MAGIC: &[u8] = b"070701";
+const NEWC_HEADER: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+/// 归档中的一项, path 不含开头的 "/" 和 "./"
+struct Node {
+    path: &'static str,
+    mode: u32,
+    data: &'static [u8],
+}
+
+impl Node {
+    #[inline]
+    fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+}
+
+static NODES: Once<Vec<Node>> = Once::new();
+
+#[derive(Clone, Copy, Debug)]
+pub struct Metadata {
+    pub is_dir: bool,
+    pub size: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: usize,
+}
+
+/// 8 位十六进制字段
+fn hex(field: &[u8]) -> Result<usize, RamfsError> {
+    let s = core::str::from_utf8(field).map_err(|_| RamfsError::Corrupt)?;
+    usize::from_str_radix(s, 16).map_err(|_| RamfsError::Corrupt)
+}
+
+fn parse(mut archive: &'static [u8]) -> Result<Vec<Node>, RamfsError> {
+    let mut nodes = Vec::new();
+    let mut offset = 0;
+    loop {
+        if archive.len() < NEWC_HEADER || &archive[..6] != NEWC_MAGIC {
+            return Err(RamfsError::Corrupt);
+        }
+        let field = |i: usize| hex(&archive[6 + i * 8..6 + (i + 1) * 8]);
+        let mode = field(1)? as u32;
+        let size = field(6)?;
+        let name_size = field(11)?;
+        // 头部和文件名之后按 4 字节对齐, 长度来自归档, 不能信任
+        let data_start = (NEWC_HEADER + offset)
+            .checked_add(name_size)
+            .and_then(|end| end.checked_next_multiple_of(4))
+            .map(|start| start - offset);
+        let data_end = data_start.and_then(|start| start.checked_add(size));
+        let (data_start, data_end) = match (data_start, data_end) {
+            (Some(start), Some(end)) if name_size > 0 && end <= archive.len() => (start, end),
+            _ => return Err(RamfsError::Corrupt),
+        };
+        let name = &archive[NEWC_HEADER..NEWC_HEADER + name_size - 1];
+        let name = core::str::from_utf8(name).map_err(|_| RamfsError::Corrupt)?;
+        if name == TRAILER {
+            return Ok(nodes);
+        }
+        let path = normalize(name);
+        if !path.is_empty() && matches!(mode & S_IFMT, S_IFDIR | S_IFREG) {
+            nodes.push(Node {
+                path,
+                mode,
+                data: &archive[data_start..data_end],
+            });
+        }
+        let next = ((offset + data_end).next_multiple_of(4) - offset).min(archive.len());
+        offset += next;
+        archive = &archive[next..];
+    }
+}
+
+#[inline]
+fn normalize(path: &str) -> &str {
+    let path = path.trim_start_matches("./").trim_matches('/');
+    if path == "." {
+        ""
+    } else {
+        path
+    }
+}
+
+// mount the initrd archive, returns the number of files and directories
+pub fn init(archive: &'static [u8]) -> Result<usize, RamfsError> {
+    let nodes = parse(archive)?;
+    let count = nodes.len();
+    NODES.call_once(|| nodes);
+    Ok(count)
+}
+
+fn nodes() -> Result<&'static [Node], RamfsError> {
+    NODES.get().map(Vec::as_slice).ok_or(RamfsError::NotMounted)
+}
+
+fn find(path: &str) -> Result<&'static Node, RamfsError> {
+    let path = normalize(path);
+    nodes()?
+        .iter()
+        .find(|node| node.path == path)
+        .ok_or(RamfsError::NotFound)
+}
+
+/// 路径是否为目录, 没有目录项的中间目录也算
+fn is_dir(path: &str) -> Result<bool, RamfsError> {
+    let path = normalize(path);
+    if path.is_empty() {
+        return nodes().map(|_| true);
+    }
+    Ok(nodes()?.iter().any(|node| {
+        (node.path == path && node.is_dir())
+            || node
+                .path
+                .strip_prefix(path)
+                .is_some_and(|rest| rest.starts_with('/'))
+    }))
+}
+
+pub fn metadata(path: &str) -> Result<Metadata, RamfsError> {
+    if is_dir(path)? {
+        return Ok(Metadata {
+            is_dir: true,
+            size: 0,
+        });
+    }
+    let node = find(path)?;
+    Ok(Metadata {
+        is_dir: false,
+        size: node.data.len(),
+    })
+}
+
+// whole file content, borrowed from the kernel image
+pub fn read(path: &str) -> Result<&'static [u8], RamfsError> {
+    match find(path) {
+        Ok(node) if !node.is_dir() => Ok(node.data),
+        Ok(_) => Err(RamfsError::IsDir),
+        Err(RamfsError::NotFound) if is_dir(path)? => Err(RamfsError::IsDir),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn open(path: &str) -> Result<File, RamfsError> {
+    read(path).map(|data| File { data, pos: 0 })
+}
+
+// direct children of a directory
+pub fn readdir(path: &str) -> Result<Vec<DirEntry>, RamfsError> {
+    if !is_dir(path)? {
+        find(path)?;
+        return Err(RamfsError::NotDir);
+    }
+    let dir = normalize(path);
+    let mut entries: Vec<DirEntry> = Vec::new();
+    for node in nodes()? {
+        let rest = if dir.is_empty() {
+            node.path
+        } else {
+            match node
+                .path
+                .strip_prefix(dir)
+                .and_then(|r| r.strip_prefix('/'))
+            {
+                Some(rest) => rest,
+                None => continue,
+            }
+        };
+        let (name, nested) = match rest.split_once('/') {
+            Some((name, _)) => (name, true),
+            None => (rest, false),
+        };
+        if entries.iter().any(|e| e.name == name) {
+            continue;
+        }
+        let is_dir = nested || node.is_dir();
+        entries.push(DirEntry {
+            name: String::from(name),
+            is_dir,
+            size: if is_dir { 0 } else { node.data.len() },
+        });
+    }
+    Ok(entries)
+}
+
+/// 打开的只读文件
+pub struct File {
+    data: &'static [u8],
+    pos: usize,
+}
+
+impl File {
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    #[inline]
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    // move the cursor, clamped to the file size
+    pub fn seek(&mut self, pos: usize) {
+        self.pos = pos.min(self.data.len());
+    }
+
+    // read from the cursor, returns 0 at the end of file
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let n = buf.len().min(self.data.len() - self.pos);
+        buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+        self.pos += n;
+        n
+    }
+
+    // the rest of the file
+    pub fn remaining(&self) -> &'static [u8] {
+        &self.data[self.pos..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{boxed::Box, format};
+
+    /// 与 xtask 的 initrd 打包相同的 newc 记录
+    fn append(archive: &mut Vec<u8>, name: &str, mode: u32, data: &[u8]) {
+        let fields = [0, mode, 0, 0, 1, 0, data.len() as u32, 0, 0, 0, 0];
+        archive.extend_from_slice(NEWC_MAGIC);
+        for field in fields {
+            archive.extend_from_slice(format!("{field:08x}").as_bytes());
+        }
+        archive.extend_from_slice(format!("{:08x}{:08x}", name.len() + 1, 0).as_bytes());
+        archive.extend_from_slice(name.as_bytes());
+        archive.push(0);
+        archive.resize(archive.len().next_multiple_of(4), 0);
+        archive.extend_from_slice(data);
+        archive.resize(archive.len().next_multiple_of(4), 0);
+    }
+
+    fn pack(files: &[(&str, u32, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        for &(name, mode, data) in files {
+            append(&mut archive, name, mode, data);
+        }
+        append(&mut archive, TRAILER, 0, &[]);
+        archive
+    }
+
+    fn leak(archive: Vec<u8>) -> &'static [u8] {
+        Box::leak(archive.into_boxed_slice())
+    }
+
+    #[test]
+    fn test_parse() {
+        let archive = pack(&[
+            (".", 0o040755, b""),
+            ("bin", 0o040755, b""),
+            ("bin/hello", 0o100644, b"hello world"),
+            ("./etc/motd", 0o100644, b"abc"),
+            ("dev/null", 0o020666, b""),
+        ]);
+        let nodes = parse(leak(archive)).unwrap();
+        // "." 和字符设备被跳过
+        assert_eq!(3, nodes.len());
+        assert_eq!("bin", nodes[0].path);
+        assert!(nodes[0].is_dir());
+        assert_eq!("bin/hello", nodes[1].path);
+        assert_eq!(b"hello world", nodes[1].data);
+        assert_eq!("etc/motd", nodes[2].path);
+        assert_eq!(b"abc", nodes[2].data);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let archive = pack(&[("hello", 0o100644, b"hello world")]);
+        // 头部不完整
+        assert_eq!(
+            Err(RamfsError::Corrupt),
+            parse(leak(archive[..60].into())).map(|_| ())
+        );
+        // 文件内容不完整
+        assert_eq!(
+            Err(RamfsError::Corrupt),
+            parse(leak(archive[..120].into())).map(|_| ())
+        );
+        // 没有 TRAILER!!!
+        let end = archive.len() - 124;
+        assert_eq!(
+            Err(RamfsError::Corrupt),
+            parse(leak(archive[..end].into())).map(|_| ())
+        );
+        assert!(parse(leak(archive)).is_ok());
+    }
+
+    #[test]
+    fn test_oversized() {
+        let mut archive = pack(&[("hello", 0o100644, b"hello world")]);
+        // filesize 超出归档, 包括加上偏移后溢出的情况
+        for size in [b"00001000", b"ffffffff"] {
+            archive[6 + 6 * 8..6 + 7 * 8].copy_from_slice(size);
+            assert_eq!(
+                Err(RamfsError::Corrupt),
+                parse(leak(archive.clone())).map(|_| ())
+            );
+        }
+        // namesize 为 0 或超出归档
+        for size in [b"00000000", b"ffffffff"] {
+            let mut archive = pack(&[("hello", 0o100644, b"hello world")]);
+            archive[6 + 11 * 8..6 + 12 * 8].copy_from_slice(size);
+            assert_eq!(Err(RamfsError::Corrupt), parse(leak(archive)).map(|_| ()));
+        }
+    }
+}
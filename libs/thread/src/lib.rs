@@ -2,14 +2,57 @@
 extern crate alloc;
 
 use core::pin::Pin;
-use alloc::boxed::Box;
+use alloc::{boxed::Box, vec::Vec};
 use spin::Once;
 use core::future::Future;
 
+/// 最低优先级, 0 为最高优先级
+pub const MAX_PRIORITY: usize = 7;
+/// 默认优先级
+pub const DEFAULT_PRIORITY: usize = 4;
+/// 使用平台默认的线程栈容量
+pub const DEFAULT_STACK_SIZE: usize = 0;
+
+/// 线程所处位置
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskState {
+    /// 正在执行 (查询者自身)
+    Running,
+    /// 在就绪队列中等待
+    Ready,
+    /// 在等待队列中睡眠
+    Sleeping,
+}
+
+/// 线程信息
+#[derive(Clone, Copy, Debug)]
+pub struct TaskInfo {
+    pub tid: usize,
+    pub io: bool,
+    pub priority: usize,
+    pub state: TaskState,
+    /// 协程数量
+    pub coroutines: usize,
+    /// 累计运行时间
+    pub cpu_time_us: usize,
+}
+
 pub trait Thread: Sync {
-    fn spawn(&self, f: Pin<Box<dyn Future<Output = ()> + Send>>, is_io: bool) -> usize;
+    fn spawn(
+        &self,
+        f: Pin<Box<dyn Future<Output = ()> + Send>>,
+        is_io: bool,
+        priority: usize,
+        stack_size: usize,
+    ) -> usize;
     fn append_task(&self, f: Pin<Box<dyn Future<Output = ()> + Send>>) -> usize;
+    fn append_cancellable(&self, f: Pin<Box<dyn Future<Output = ()> + Send>>) -> (usize, usize);
+    fn set_priority(&self, tid: usize, priority: usize) -> bool;
+    fn kill(&self, tid: usize) -> bool;
+    fn cancel(&self, cid: usize) -> bool;
+    fn tasks(&self) -> Vec<TaskInfo>;
     fn yields(&self);
+    fn shutdown(&self, error: bool);
 }
 
 static THREAD: Once<&'static dyn Thread> = Once::new();
@@ -22,7 +65,27 @@ pub fn spawn<F>(f: F, is_io: bool) -> usize
 where
     F: Future<Output = ()> + Send + 'static,
 {
-    THREAD.wait().spawn(Box::pin(f), is_io)
+    spawn_with_priority(f, is_io, DEFAULT_PRIORITY)
+}
+
+pub fn spawn_with_priority<F>(f: F, is_io: bool, priority: usize) -> usize
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    spawn_with_stack(f, is_io, priority, DEFAULT_STACK_SIZE)
+}
+
+// spawn a thread with a stack of stack_size bytes, for deep recursion or large futures
+pub fn spawn_with_stack<F>(f: F, is_io: bool, priority: usize, stack_size: usize) -> usize
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    THREAD.wait().spawn(Box::pin(f), is_io, priority, stack_size)
+}
+
+// set priority of thread tid
+pub fn set_priority(tid: usize, priority: usize) -> bool {
+    THREAD.wait().set_priority(tid, priority)
 }
 
 // append_task to current thread
@@ -36,3 +99,56 @@ where
 pub fn yields() {
     THREAD.wait().yields();
 }
+
+// power off the machine
+pub fn shutdown(error: bool) {
+    THREAD.wait().shutdown(error);
+}
+
+/// 协程取消令牌
+#[derive(Clone, Copy, Debug)]
+pub struct CancelToken {
+    tid: usize,
+    cid: usize,
+}
+
+impl CancelToken {
+    /// 协程所在线程
+    pub fn tid(&self) -> usize {
+        self.tid
+    }
+
+    /// 协程 ID
+    pub fn cid(&self) -> usize {
+        self.cid
+    }
+
+    /// 协作式取消: 协程在下一次被轮询前被丢弃, 请求未能送达时返回 false
+    pub fn cancel(&self) -> bool {
+        cancel(self.cid)
+    }
+}
+
+// append_task to current thread, return a token to cancel it
+pub fn append_cancellable<F>(f: F) -> CancelToken
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let (tid, cid) = THREAD.wait().append_cancellable(Box::pin(f));
+    CancelToken { tid, cid }
+}
+
+// list all threads, like ps
+pub fn tasks() -> Vec<TaskInfo> {
+    THREAD.wait().tasks()
+}
+
+// kill thread tid and drop all of its coroutines
+pub fn kill(tid: usize) -> bool {
+    THREAD.wait().kill(tid)
+}
+
+// cancel coroutine cid, return false if the request could not be delivered
+pub fn cancel(cid: usize) -> bool {
+    THREAD.wait().cancel(cid)
+}
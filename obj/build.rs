@@ -6,6 +6,45 @@
 
     println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rerun-if-env-changed=LOG");
+
+    // xtask 通过 USER_ELFS 传入需要打包的用户程序, 以逗号分隔
+    println!("cargo:rerun-if-env-changed=USER_ELFS");
+    let elfs = env::var("USER_ELFS").unwrap_or_default();
+    let mut table = String::from("static USER_ELFS: &[(&str, &[u8])] = &[\n");
+    for path in elfs.split(',').filter(|path| !path.is_empty()) {
+        println!("cargo:rerun-if-changed={path}");
+        let name = PathBuf::from(path);
+        let name = name.file_stem().unwrap().to_str().unwrap();
+        table.push_str(&format!("    ({name:?}, include_bytes!({path:?})),\n"));
+    }
+    table.push_str("];\n");
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("user_elfs.rs");
+    fs::write(out, table).unwrap();
+
+    // xtask 打包的 initrd, 单独构建 obj 时为空
+    println!("cargo:rerun-if-env-changed=INITRD");
+    let initrd = match env::var("INITRD") {
+        Ok(path) if !path.is_empty() => {
+            println!("cargo:rerun-if-changed={path}");
+            format!("static INITRD: &[u8] = include_bytes!({path:?});\n")
+        }
+        _ => String::from("static INITRD: &[u8] = &[];\n"),
+    };
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("initrd.rs");
+    fs::write(out, initrd).unwrap();
+
+    // xtask 从上一次构建的 ELF 生成的符号表, 单独构建 obj 时为空
+    println!("cargo:rerun-if-env-changed=SYMBOLS");
+    let symbols = match env::var("SYMBOLS") {
+        Ok(path) if !path.is_empty() => {
+            println!("cargo:rerun-if-changed={path}");
+            format!("static SYMBOLS: &str = include_str!({path:?});\n")
+        }
+        _ => String::from("static SYMBOLS: &str = \"\";\n"),
+    };
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("symbols.rs");
+    fs::write(out, symbols).unwrap();
+
     if cfg!(not(feature = "std")) {
         println!("cargo:rustc-link-arg=-T{}", ld.display());
     }
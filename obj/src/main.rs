@@ -5,21 +5,42 @@
 
 extern crate alloc;
 
-use alloc::boxed::Box;
+#[cfg(not(feature = "std"))]
+mod panic;
+
+use alloc::{boxed::Box, vec, vec::Vec};
 use executor::{IRQ, async_yield, async_wait_irq};
 use thread::append_task;
-use core::{future::Future, pin::Pin, time::Duration};
+use core::{future::Future, pin::Pin, task::Waker, time::Duration};
 
-use platform::{Platform, PlatformImpl, MACADDR};
+use platform::{Platform, PlatformImpl, DEFAULT_PRIORITY, MACADDR};
 use stdio::log::info;
 use timer::get_time_us;
 
+// xtask 打包的用户程序: (名称, ELF)
+include!(concat!(env!("OUT_DIR"), "/user_elfs.rs"));
+// xtask 打包的 initrd (cpio newc)
+include!(concat!(env!("OUT_DIR"), "/initrd.rs"));
+
 #[no_mangle]
 #[repr(align(2))]
 fn obj_main() {
+    // 控制台任务启动后输出经过缓冲区
+    PlatformImpl::spawn(stdio::console_task(), true, DEFAULT_PRIORITY);
     init_ethernet();
     thread::init(&ThreadImpl);
-    PlatformImpl::spawn(async { app::app_main().await }, true);
+    mem::init(&MemoryImpl);
+    block::init(&BlockImpl);
+    if !INITRD.is_empty() {
+        match ramfs::init(INITRD) {
+            Ok(count) => info!("initrd: {count} entries"),
+            Err(e) => stdio::log::warn!("initrd: {e:?}"),
+        }
+    }
+    PlatformImpl::spawn(async { app::app_main().await }, true, DEFAULT_PRIORITY);
+    for (name, elf) in USER_ELFS {
+        PlatformImpl::exec(elf, &[*name], &[]);
+    }
 }
 
 fn init_ethernet() {
@@ -36,18 +57,10 @@ fn init_ethernet() {
             });
         },
         true,
+        DEFAULT_PRIORITY,
     );
 }
 
-#[cfg(not(feature = "std"))]
-#[panic_handler]
-fn panic(info: &core::panic::PanicInfo) -> ! {
-    stdio::log::error!("{info}");
-    loop {}
-    // PlatformImpl::shutdown(true);
-    // unreachable!();
-}
-
 struct PhyNet;
 
 impl net::PhyNet for PhyNet {
@@ -71,14 +84,126 @@ impl net::PhyNet for PhyNet {
 struct ThreadImpl;
 
 impl thread::Thread for ThreadImpl {
-    fn spawn(&self, f: Pin<Box<dyn Future<Output = ()> + Send>>, is_io: bool) -> usize {
-        PlatformImpl::spawn(f, is_io)
+    fn spawn(
+        &self,
+        f: Pin<Box<dyn Future<Output = ()> + Send>>,
+        is_io: bool,
+        priority: usize,
+        stack_size: usize,
+    ) -> usize {
+        PlatformImpl::spawn_with_stack(f, is_io, priority, stack_size)
     }
     fn append_task(&self, f: Pin<Box<dyn Future<Output = ()> + Send>>) -> usize {
         PlatformImpl::append_task(f)
     }
 
+    fn append_cancellable(&self, f: Pin<Box<dyn Future<Output = ()> + Send>>) -> (usize, usize) {
+        PlatformImpl::append_cancellable(f)
+    }
+
+    fn set_priority(&self, tid: usize, priority: usize) -> bool {
+        PlatformImpl::set_priority(tid, priority)
+    }
+
+    fn kill(&self, tid: usize) -> bool {
+        PlatformImpl::kill(tid)
+    }
+
+    fn cancel(&self, cid: usize) -> bool {
+        PlatformImpl::cancel(cid)
+    }
+
+    fn tasks(&self) -> Vec<thread::TaskInfo> {
+        use platform::{TaskInfo, TaskState};
+        // 两次查询之间线程数量可能增加，多留一些空间
+        let mut buf = vec![TaskInfo::default(); PlatformImpl::tasks(&mut []) + 8];
+        let count = PlatformImpl::tasks(&mut buf).min(buf.len());
+        buf[..count]
+            .iter()
+            .map(|info| thread::TaskInfo {
+                tid: info.tid,
+                io: info.io,
+                priority: info.priority,
+                state: match info.state {
+                    TaskState::Running => thread::TaskState::Running,
+                    TaskState::Ready => thread::TaskState::Ready,
+                    TaskState::Sleeping => thread::TaskState::Sleeping,
+                },
+                coroutines: info.coroutines,
+                cpu_time_us: info.cpu_time_us,
+            })
+            .collect()
+    }
+
     fn yields(&self) {
         PlatformImpl::sys_yield();
     }
+
+    fn shutdown(&self, error: bool) {
+        stdio::force_sync();
+        PlatformImpl::shutdown(error);
+    }
+}
+
+struct MemoryImpl;
+
+impl mem::Memory for MemoryImpl {
+    fn heap_stats(&self) -> mem::HeapStats {
+        let stats = PlatformImpl::heap_stats();
+        mem::HeapStats {
+            total: stats.total,
+            in_use: stats.in_use,
+            peak: stats.peak,
+            allocs: stats.allocs,
+            deallocs: stats.deallocs,
+            failures: stats.failures,
+            largest_free: stats.largest_free,
+        }
+    }
+
+    fn track(&self, enable: bool) {
+        PlatformImpl::heap_track(enable);
+    }
+
+    fn records(&self) -> Vec<mem::AllocRecord> {
+        use platform::AllocRecord;
+        // 查询期间仍会有新的分配，多留一些空间
+        let count = PlatformImpl::heap_records(&mut []);
+        let mut buf = vec![AllocRecord::default(); count + 64];
+        let count = PlatformImpl::heap_records(&mut buf).min(buf.len());
+        buf[..count]
+            .iter()
+            .map(|record| mem::AllocRecord {
+                ptr: record.ptr,
+                size: record.size,
+                tid: (record.tid != usize::MAX).then_some(record.tid),
+                sites: record.sites,
+            })
+            .collect()
+    }
+
+    fn dump(&self) {
+        PlatformImpl::heap_dump();
+    }
+}
+
+struct BlockImpl;
+
+impl block::BlockDevice for BlockImpl {
+    fn sectors(&self) -> u64 {
+        PlatformImpl::blk_sectors()
+    }
+
+    fn submit(&self, sector: u64, buf: Vec<u8>, write: bool) -> Result<usize, Vec<u8>> {
+        PlatformImpl::blk_submit(sector, buf, write)
+    }
+
+    fn poll(&self, id: usize, waker: &Waker) -> Option<Result<Vec<u8>, block::BlockError>> {
+        PlatformImpl::blk_poll(id, waker)
+            .map(|(buf, ok)| ok.then_some(buf).ok_or(block::BlockError::Io))
+    }
+
+    fn forget(&self, id: usize) {
+        PlatformImpl::blk_forget(id);
+    }
 }
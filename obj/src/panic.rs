@@ -0,0 +1,59 @@
+//! panic 时打印回溯和调度器状态, 然后关机, 让 qemu 以失败退出
+
+use core::{
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use platform::{Platform, PlatformImpl};
+use stdio::println;
+
+// xtask 生成的符号表: 每行 "十六进制地址 名字", 按地址升序
+include!(concat!(env!("OUT_DIR"), "/symbols.rs"));
+
+const MAX_DEPTH: usize = 32;
+
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    stdio::force_sync();
+    // 打印回溯或调度器状态时再次 panic, 直接关机
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        println!("panic while panicking: {info}");
+        PlatformImpl::shutdown(true);
+        loop {}
+    }
+    stdio::log::error!("{info}");
+
+    let mut frames = [0usize; MAX_DEPTH];
+    let depth = PlatformImpl::backtrace(&mut frames);
+    println!("backtrace:");
+    for (i, ra) in frames[..depth].iter().enumerate() {
+        // 返回地址可能已经是下一个函数, 用调用指令所在的位置查找
+        match lookup(ra.saturating_sub(1)) {
+            Some((name, addr)) => println!("  #{i:<2} {ra:#x} {name}+{:#x}", ra - addr),
+            None => println!("  #{i:<2} {ra:#x} ?"),
+        }
+    }
+    PlatformImpl::sched_dump();
+    PlatformImpl::shutdown(true);
+    loop {}
+}
+
+/// 找到包含 pc 的函数, 返回名字和起始地址
+fn lookup(pc: usize) -> Option<(&'static str, usize)> {
+    let mut found = None;
+    for line in SYMBOLS.lines() {
+        let Some((addr, name)) = line.split_once(' ') else {
+            continue;
+        };
+        let Ok(addr) = usize::from_str_radix(addr, 16) else {
+            continue;
+        };
+        if addr > pc {
+            break;
+        }
+        found = Some((name, addr));
+    }
+    found
+}
@@ -22,8 +22,8 @@ fn obj_main() {
 fn main() {
     // mem is not needed in std environment
     // stdio
-    stdio::set_log_level(option_env!("LOG"));
     stdio::init(&Stdio);
+    stdio::set_log_level(option_env!("LOG"));
 
     executor::init(&basic::Executor);
 
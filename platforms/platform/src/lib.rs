@@ -1,6 +1,61 @@
 #![no_std]
+extern crate alloc;
 
-use core::future::Future;
+use alloc::vec::Vec;
+use core::{future::Future, task::Waker};
+
+/// 线程所处位置
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TaskState {
+    /// 正在执行 (查询者自身)
+    Running,
+    /// 在就绪队列中等待
+    #[default]
+    Ready,
+    /// 在 TIMERS 中等待唤醒
+    Sleeping,
+}
+
+/// 线程信息, 用于 ps
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TaskInfo {
+    pub tid: usize,
+    pub io: bool,
+    pub priority: usize,
+    pub state: TaskState,
+    /// 协程数量
+    pub coroutines: usize,
+    /// 累计运行时间
+    pub cpu_time_us: usize,
+}
+
+/// 分配记录保存的返回地址层数
+pub const ALLOC_SITE_DEPTH: usize = 4;
+
+/// 堆使用统计, 单位为字节
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HeapStats {
+    pub total: usize,
+    pub in_use: usize,
+    pub peak: usize,
+    pub allocs: usize,
+    pub deallocs: usize,
+    /// 分配失败次数
+    pub failures: usize,
+    /// 最大可分配块
+    pub largest_free: usize,
+}
+
+/// 未释放的分配
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AllocRecord {
+    pub ptr: usize,
+    pub size: usize,
+    /// 分配时所在线程, usize::MAX 表示不在线程中
+    pub tid: usize,
+    /// 调用点返回地址
+    pub sites: [usize; ALLOC_SITE_DEPTH],
+}
 
 pub trait Platform {
     fn console_getchar() -> u8;
@@ -12,6 +67,19 @@ pub trait Platform {
         }
     }
 
+    // 设备的中断号, 给 executor::async_wait_irq 使用; 没有设备或不支持中断时为 None
+    fn console_irq() -> Option<u32> {
+        None
+    }
+
+    fn net_irq() -> Option<u32> {
+        None
+    }
+
+    fn blk_irq() -> Option<u32> {
+        None
+    }
+
     // net: 默认不要求实现
     fn net_receive(_buf: &mut [u8]) -> usize {
         0
@@ -27,14 +95,45 @@ pub trait Platform {
         true
     }
 
-    // thread
-    fn spawn<F>(_f: F, _is_io: bool) -> usize
+    // block: 扇区数, 0 表示没有块设备
+    fn blk_sectors() -> u64 {
+        0
+    }
+
+    // 提交单扇区读写请求, 返回请求号; 队列满时返回 buf
+    fn blk_submit(_sector: u64, buf: Vec<u8>, _write: bool) -> Result<usize, Vec<u8>> {
+        Err(buf)
+    }
+
+    // 取回已完成的请求 (buf, 是否成功), 未完成时记录 waker
+    fn blk_poll(_id: usize, _waker: &Waker) -> Option<(Vec<u8>, bool)> {
+        None
+    }
+
+    // 放弃请求
+    fn blk_forget(_id: usize) {}
+
+    // thread: priority 0 最高, 数值越大优先级越低
+    fn spawn<F>(_f: F, _is_io: bool, _priority: usize) -> usize
     where
         F: Future<Output = ()> + Send + 'static,
     {
         0
     }
-    
+
+    // 指定线程栈容量, stack_size 为 0 时使用平台默认值
+    fn spawn_with_stack<F>(f: F, is_io: bool, priority: usize, _stack_size: usize) -> usize
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        Self::spawn(f, is_io, priority)
+    }
+
+    // 修改线程优先级, 线程不存在时返回 false
+    fn set_priority(_tid: usize, _priority: usize) -> bool {
+        false
+    }
+
     // append_task to current thread
     fn append_task<F>(_f: F) -> usize
     where
@@ -44,6 +143,34 @@ pub trait Platform {
     }
 
 
+    // append_task and return (tid, cid), cid 用于 cancel
+    fn append_cancellable<F>(_f: F) -> (usize, usize)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        (0, 0)
+    }
+
+    // 结束线程, 线程不存在时返回 false
+    fn kill(_tid: usize) -> bool {
+        false
+    }
+
+    // 取消协程, 请求未能送达时返回 false
+    fn cancel(_cid: usize) -> bool {
+        false
+    }
+
+    // 将线程信息写入 buf, 返回线程总数
+    fn tasks(_buf: &mut [TaskInfo]) -> usize {
+        0
+    }
+
+    // 加载 ELF 用户程序并在新线程中执行, 返回 tid
+    fn exec(_elf: &[u8], _argv: &[&str], _envp: &[&str]) -> Option<usize> {
+        None
+    }
+
     fn sys_yield() {}
 
     fn wait(_delay: core::time::Duration) {}
@@ -53,6 +180,33 @@ pub trait Platform {
         (0, 0)
     }
 
+    // 堆使用统计
+    fn heap_stats() -> HeapStats {
+        HeapStats::default()
+    }
+
+    // 开关分配记录, 打开时清空已有记录
+    fn heap_track(_enable: bool) {}
+
+    // 将未释放的分配记录写入 buf, 返回记录总数
+    fn heap_records(_buf: &mut [AllocRecord]) -> usize {
+        0
+    }
+
+    // 打印堆统计和分配记录
+    fn heap_dump() {}
+
+    // 打印调度事件
+    fn trace_dump() {}
+
+    // 沿帧指针回溯, 将返回地址写入 buf, 返回层数
+    fn backtrace(_buf: &mut [usize]) -> usize {
+        0
+    }
+
+    // 打印当前线程和调度队列, panic 时使用, 不能等待锁
+    fn sched_dump() {}
+
     // machine
     fn frequency() -> usize;
     fn rdtime() -> usize;
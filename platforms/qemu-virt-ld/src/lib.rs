@@ -80,7 +80,7 @@ macro_rules! boot0 {
 
             core::arch::asm!(
                 "la sp, __end",
-                "j  {main}",
+                "tail {main}",
                 main = sym $entry,
                 options(noreturn),
             )
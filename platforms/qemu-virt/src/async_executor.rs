@@ -7,7 +7,7 @@ use alloc::{collections::BTreeMap, sync::Arc};
 use core::{
     future::Future,
     pin::Pin,
-    sync::atomic::{AtomicU64, Ordering},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
     task::{Context, Poll, Waker},
 };
 use crossbeam_queue::ArrayQueue;
@@ -18,6 +18,19 @@ use crate::{syscall::sys_yield, TASKNUM};
 
 pub type PinBoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
 
+/// 正在轮询的协程, 切换线程时由 Task::run 保存和恢复
+pub(crate) static CURRENT_CID: AtomicUsize = AtomicUsize::new(NO_COROUTINE);
+pub(crate) const NO_COROUTINE: usize = usize::MAX;
+
+/// 当前协程 ID, 不在协程中时为 None
+#[inline]
+pub fn current_cid() -> Option<usize> {
+    match CURRENT_CID.load(Ordering::Relaxed) {
+        NO_COROUTINE => None,
+        cid => Some(cid),
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 struct AsyncTaskId(u64);
 
@@ -42,6 +55,11 @@ impl AsyncTask {
             io: false,
         }
     }
+
+    /// 协程 ID，用于取消协程
+    pub fn id(&self) -> usize {
+        self.id.0 as usize
+    }
 }
 
 struct TaskWaker {
@@ -84,6 +102,8 @@ pub struct Executor {
     waker_cache: BTreeMap<AsyncTaskId, Waker>,
     current: AsyncTaskId,
     ticks: usize,
+    /// 待取消的协程, 由调度器写入, 在下一次 run_ready_tasks 时丢弃
+    cancelled: ArrayQueue<AsyncTaskId>,
 }
 
 impl Executor {
@@ -94,6 +114,7 @@ impl Executor {
             waker_cache: BTreeMap::new(),
             current: AsyncTaskId(0),
             ticks: 0,
+            cancelled: ArrayQueue::new(TASKNUM),
         }
     }
 }
@@ -115,12 +136,32 @@ impl Executor {
         self.tasks.len()
     }
 
+    /// 请求取消协程 id，请求队列已满时返回 false
+    ///
+    /// 协作式取消: 正在执行的协程不会被打断，在下一次轮询前丢弃其 future。
+    /// 调度器调用时执行器可能正在运行，这里只写入无锁队列，不读取 tasks，
+    /// 不属于此执行器的 id 由 drop_cancelled 忽略
+    pub fn cancel(&self, id: usize) -> bool {
+        self.cancelled.push(AsyncTaskId(id as u64)).is_ok()
+    }
+
+    /// 丢弃所有协程
+    pub fn clear(&mut self) {
+        while self.task_queue.pop().is_ok() {}
+        while self.cancelled.pop().is_ok() {}
+        self.tasks.clear();
+        self.waker_cache.clear();
+    }
+
     fn run_ready_tasks(&mut self) {
         let tasks = &mut self.tasks;
         let task_queue = &mut self.task_queue;
         let waker_cache = &mut self.waker_cache;
+        let cancelled = &self.cancelled;
 
+        drop_cancelled(cancelled, tasks, waker_cache);
         while let Ok(task_id) = task_queue.pop() {
+            drop_cancelled(cancelled, tasks, waker_cache);
             self.current = task_id;
             let task = match tasks.get_mut(&task_id) {
                 Some(task) => task,
@@ -131,7 +172,10 @@ impl Executor {
                 .or_insert_with(|| TaskWaker::new(task_id, task_queue.clone()));
             let mut context = Context::from_waker(waker);
             let handle = Pin::new(&mut task.future);
-            match handle.poll(&mut context) {
+            CURRENT_CID.store(task_id.0 as usize, Ordering::Relaxed);
+            let poll = handle.poll(&mut context);
+            CURRENT_CID.store(NO_COROUTINE, Ordering::Relaxed);
+            match poll {
                 Poll::Ready(()) => {
                     // task done -> remove it and its cached waker
                     tasks.remove(&task_id);
@@ -170,12 +214,27 @@ impl Executor {
                 waker_cache.remove(&task_id);
             }
 
+            // 待取消的请求跟随协程转移
+            let new_cancelled = ArrayQueue::new(TASKNUM);
+            for _ in 0..self.cancelled.len() {
+                let Ok(task_id) = self.cancelled.pop() else {
+                    break;
+                };
+                let queue = if new_tasks.contains_key(&task_id) {
+                    &new_cancelled
+                } else {
+                    &self.cancelled
+                };
+                let _ = queue.push(task_id);
+            }
+
             return Some(Executor {
                 task_queue: new_task_queue,
                 tasks: new_tasks,
                 waker_cache: BTreeMap::new(),
                 current: AsyncTaskId(0),
                 ticks: 0,
+                cancelled: new_cancelled,
             });
         }
         None
@@ -192,3 +251,15 @@ impl Executor {
         }
     }
 }
+
+/// 丢弃被取消的协程及其 waker 缓存, 不在 tasks 中的 id 直接忽略
+fn drop_cancelled(
+    cancelled: &ArrayQueue<AsyncTaskId>,
+    tasks: &mut BTreeMap<AsyncTaskId, AsyncTask>,
+    waker_cache: &mut BTreeMap<AsyncTaskId, Waker>,
+) {
+    while let Ok(task_id) = cancelled.pop() {
+        tasks.remove(&task_id);
+        waker_cache.remove(&task_id);
+    }
+}
@@ -0,0 +1,253 @@
+#![allow(unused)]
+
+//! 从 OpenSBI 传入的设备树中获取内存和设备信息, 解析失败时使用 qemu virt 的默认布局
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::ops::Range;
+use dtb_walker::{Dtb, DtbObj, HeaderError, Property, WalkOperation::*};
+use spin::Once;
+use stdio::log;
+
+/// qemu virt 默认的物理内存
+pub const DEFAULT_MEMORY: Range<usize> = 0x8000_0000..0x8800_0000;
+
+/// 板级信息
+#[derive(Debug)]
+pub struct BoardInfo {
+    /// 物理内存
+    pub memory: Range<usize>,
+    /// rdtime 频率
+    pub timebase: usize,
+    pub uart: usize,
+    pub uart_irq: u32,
+    pub plic: Range<usize>,
+    /// virtio-mmio 槽位: (地址, 中断号)
+    pub virtio_mmio: Vec<(usize, u32)>,
+    /// PCIe 配置空间
+    pub pci_ecam: Range<usize>,
+    /// PCIe 32 位 MMIO 窗口, 用于分配 BAR
+    pub pci_mmio: Range<usize>,
+    /// interrupt-map-mask: (phys.hi, pin)
+    pub pci_irq_mask: (u32, u32),
+    /// interrupt-map: (phys.hi, pin, irq)
+    pub pci_irq_map: Vec<(u32, u32, u32)>,
+    /// 设备树自身所在的内存, 不能交给页帧分配器
+    pub dtb: Range<usize>,
+}
+
+impl BoardInfo {
+    /// qemu virt 默认布局
+    fn qemu_virt() -> Self {
+        Self {
+            memory: DEFAULT_MEMORY,
+            timebase: 10_000_000,
+            uart: 0x1000_0000,
+            uart_irq: 10,
+            plic: 0x0c00_0000..0x0c60_0000,
+            virtio_mmio: (0..8)
+                .map(|i| (0x1000_1000 + i * 0x1000, 1 + i as u32))
+                .collect(),
+            pci_ecam: 0x3000_0000..0x4000_0000,
+            pci_mmio: 0x4000_0000..0x8000_0000,
+            pci_irq_mask: (0x1800, 0x7),
+            // INTx 在 4 个 IRQ 之间轮换
+            pci_irq_map: (0..4)
+                .flat_map(|slot| {
+                    (1..=4).map(move |pin| (slot << 11, pin, 32 + (slot + pin - 1) % 4))
+                })
+                .collect(),
+            dtb: 0..0,
+        }
+    }
+
+    /// PCI 设备 dev 的 INTx 引脚 pin (1-4) 对应的 PLIC 中断号
+    pub fn pci_irq(&self, dev: usize, pin: u32) -> Option<u32> {
+        let hi = ((dev as u32) << 11) & self.pci_irq_mask.0;
+        let pin = pin & self.pci_irq_mask.1;
+        self.pci_irq_map
+            .iter()
+            .find(|&&(h, p, _)| h == hi && p == pin)
+            .map(|&(_, _, irq)| irq)
+    }
+}
+
+static BOARD: Once<BoardInfo> = Once::new();
+
+#[inline]
+pub fn board() -> &'static BoardInfo {
+    BOARD.wait()
+}
+
+/// rdtime 频率
+#[inline]
+pub fn timebase() -> usize {
+    board().timebase
+}
+
+#[inline]
+fn be_u32(value: &[u8], i: usize) -> u32 {
+    u32::from_be_bytes(value[i * 4..i * 4 + 4].try_into().unwrap())
+}
+
+#[inline]
+fn be_u64(value: &[u8], i: usize) -> usize {
+    ((be_u32(value, i) as usize) << 32) | be_u32(value, i + 1) as usize
+}
+
+unsafe fn open(dtb: usize) -> Result<Dtb<'static>, HeaderError> {
+    Dtb::from_raw_parts_filtered(dtb as *const u8, |e| {
+        matches!(
+            e,
+            HeaderError::Misaligned(4) | HeaderError::LastCompVersion(_)
+        )
+    })
+}
+
+/// 只读取物理内存范围, 不需要堆, 在堆初始化前用于确定堆的大小
+pub fn memory(dtb: usize) -> Option<Range<usize>> {
+    let tree = unsafe { open(dtb) }.ok()?;
+    let mut memory = None;
+    tree.walk(|ctx, obj| match obj {
+        DtbObj::SubNode { name } if ctx.level() == 0 && name.starts_with(b"memory") => StepInto,
+        DtbObj::SubNode { .. } => StepOver,
+        DtbObj::Property(Property::Reg(mut reg)) => {
+            if memory.is_none() {
+                memory = reg.next();
+            }
+            StepOver
+        }
+        DtbObj::Property(_) => StepOver,
+    });
+    memory
+}
+
+/// 解析设备树, 需要在堆初始化后调用; 失败时使用默认布局并返回错误
+pub fn init(dtb: usize) -> Result<(), HeaderError> {
+    let mut info = BoardInfo::qemu_virt();
+    let defaults = core::mem::take(&mut info.virtio_mmio);
+    let ans = unsafe { open(dtb) }.map(|tree| {
+        info.dtb = dtb..dtb + tree.total_size();
+        walk(&tree, &mut info);
+    });
+    if info.virtio_mmio.is_empty() {
+        info.virtio_mmio = defaults;
+    }
+    BOARD.call_once(|| info);
+    ans
+}
+
+fn walk(tree: &Dtb, info: &mut BoardInfo) {
+    let mut uart_found = false;
+    tree.walk(|ctx, obj| match obj {
+        DtbObj::SubNode { name } => {
+            if ctx.level() == 0 {
+                if name == b"cpus" || name == b"soc" || name.starts_with(b"memory") {
+                    StepInto
+                } else {
+                    StepOver
+                }
+            } else if ctx.last() == b"soc" {
+                // 只使用第一个串口
+                if (name.starts_with(b"uart") && !uart_found)
+                    || name.starts_with(b"plic")
+                    || name.starts_with(b"pci")
+                {
+                    StepInto
+                } else if name.starts_with(b"virtio_mmio") {
+                    // reg 和 interrupts 的顺序不固定, 先占位
+                    info.virtio_mmio.push((0, 0));
+                    StepInto
+                } else {
+                    StepOver
+                }
+            } else {
+                StepOver
+            }
+        }
+        DtbObj::Property(Property::Reg(mut reg)) => {
+            let node = ctx.last();
+            if let Some(range) = reg.next() {
+                if node.starts_with(b"memory") {
+                    info.memory = range;
+                } else if node.starts_with(b"uart") {
+                    info.uart = range.start;
+                    uart_found = true;
+                } else if node.starts_with(b"plic") {
+                    info.plic = range;
+                } else if node.starts_with(b"pci") {
+                    info.pci_ecam = range;
+                } else if node.starts_with(b"virtio_mmio") {
+                    if let Some(slot) = info.virtio_mmio.last_mut() {
+                        slot.0 = range.start;
+                    }
+                }
+            }
+            StepOver
+        }
+        DtbObj::Property(Property::General { name, value }) => {
+            let name = name.as_bytes();
+            let node = ctx.last();
+            if name == b"timebase-frequency" && value.len() == 4 {
+                info.timebase = be_u32(value, 0) as usize;
+            } else if name == b"interrupts" && node.starts_with(b"uart") && value.len() >= 4 {
+                info.uart_irq = be_u32(value, 0);
+            } else if name == b"interrupts" && node.starts_with(b"virtio_mmio") && value.len() >= 4
+            {
+                if let Some(slot) = info.virtio_mmio.last_mut() {
+                    slot.1 = be_u32(value, 0);
+                }
+            } else if node.starts_with(b"pci") {
+                parse_pci(name, value, info);
+            }
+            StepOver
+        }
+        DtbObj::Property(_) => StepOver,
+    });
+}
+
+/// 解析 PCIe 主桥的 ranges 和 interrupt-map
+///
+/// 假设 #address-cells = 3, 父总线 #address-cells = 2, PLIC #interrupt-cells = 1
+fn parse_pci(name: &[u8], value: &[u8], info: &mut BoardInfo) {
+    if name == b"ranges" {
+        // (phys.hi, phys.mid, phys.lo, parent.hi, parent.lo, size.hi, size.lo)
+        for entry in value.chunks_exact(7 * 4) {
+            let space = (be_u32(entry, 0) >> 24) & 0x3;
+            // 0x2: 32 位 MMIO
+            if space == 0x2 {
+                let start = be_u64(entry, 3);
+                info.pci_mmio = start..start + be_u64(entry, 5);
+            }
+        }
+    } else if name == b"interrupt-map-mask" && value.len() == 4 * 4 {
+        info.pci_irq_mask = (be_u32(value, 0), be_u32(value, 3));
+    } else if name == b"interrupt-map" {
+        // (phys.hi, phys.mid, phys.lo, pin, phandle, irq)
+        info.pci_irq_map = value
+            .chunks_exact(6 * 4)
+            .map(|entry| (be_u32(entry, 0), be_u32(entry, 3), be_u32(entry, 5)))
+            .collect();
+    }
+}
+
+/// 打印板级信息
+pub fn print() {
+    let info = board();
+    log::info!(
+        "memory {:#x?}, timebase {}, uart {:#x} (irq {}), plic {:#x?}",
+        info.memory,
+        info.timebase,
+        info.uart,
+        info.uart_irq,
+        info.plic
+    );
+    log::info!("virtio-mmio {} slots", info.virtio_mmio.len());
+    log::info!(
+        "pci ecam {:#x?}, mmio {:#x?}, dtb {:#x?}",
+        info.pci_ecam,
+        info.pci_mmio,
+        info.dtb
+    );
+}
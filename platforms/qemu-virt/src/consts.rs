@@ -1,7 +1,7 @@
 // 内核堆内存
 pub const MM_SIZE: usize = 32 << 20;
-// 物理内存容量
-pub const MEMORY_SIZE: usize = 128 << 20 - 1;
+// 内核堆占内核之后物理内存的 1/HEAP_FRACTION, 其余交给页帧分配器
+pub const HEAP_FRACTION: usize = 2;
 
 // 单个线程可容纳协程数量
 pub const TASKNUM: usize = 300;
@@ -12,12 +12,35 @@ pub const SYSCALL_GET_TID: usize = 102;
 pub const SYSCALL_APPEND_TASK: usize = 103;
 pub const SYSCALL_YIELD: usize = 104;
 pub const SYSCALL_EXIT: usize = 105;
+pub const SYSCALL_SET_PRIORITY: usize = 106;
+pub const SYSCALL_KILL: usize = 107;
+pub const SYSCALL_CANCEL: usize = 108;
+pub const SYSCALL_TASKS: usize = 109;
+
+// USER SYSCALL, 与 Linux 编号一致, 其余编号转发给内核系统调用
+pub const USER_SYSCALL_WRITE: usize = 64;
+pub const USER_SYSCALL_EXIT: usize = 93;
+
+// 线程优先级: 0 最高, 数值越大优先级越低
+pub const MAX_PRIORITY: usize = 7;
+pub const DEFAULT_PRIORITY: usize = 4;
+
+// 调度事件环形缓冲区容量
+pub const TRACE_SIZE: usize = 1024;
 
 // STACK_SIZE FOR THREAD
 pub const STACK_SIZE: usize = 0x8000;
+// 线程栈最小容量与对齐
+pub const MIN_STACK_SIZE: usize = 0x1000;
+// 栈底金丝雀: 字数与填充值
+pub const STACK_CANARY_WORDS: usize = 8;
+pub const STACK_CANARY: usize = 0xdead_beef_cafe_f00d;
+
+// 用户栈: 栈顶位于 Sv39 低半区顶端, 留出一页保护页
+pub const USER_STACK_TOP: usize = (1 << 38) - 0x1000;
+pub const USER_STACK_SIZE: usize = 0x8000;
 
 // TIMER
-pub const CLOCK_FREQ: usize = 12500000;
 pub const TICKS_PER_SEC: usize = 100;
 pub const MILLI_PER_SEC: usize = 1_000;
 pub const MICRO_PER_SEC: usize = 1_000_000;
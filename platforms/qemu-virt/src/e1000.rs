@@ -1,21 +1,17 @@
-extern crate alloc;
-use alloc::{
-    alloc::{alloc, dealloc},
+use crate::{
+    frame::{dma_alloc, dma_dealloc},
+    net,
+    pci::{PciDevice, PciDriver},
 };
-
-use core::alloc::Layout;
+use core::sync::atomic::{AtomicU32, Ordering};
 use isomorphic_drivers::{
     net::ethernet::{intel::e1000::E1000, structs::EthernetAddress as DriverEthernetAddress},
     provider,
 };
 use spin::{Lazy, Mutex};
 
-
-use futures::task::AtomicWaker;
-
-pub static ASYNC_WAIT_WAKER: AtomicWaker = AtomicWaker::new();
-
-// pub const E1000_IRQ: usize = 33;
+// PLIC 中断号, 由 PCI INTx 路由决定
+pub static E1000_IRQ: AtomicU32 = AtomicU32::new(0);
 
 pub struct Provider;
 
@@ -23,22 +19,33 @@ impl provider::Provider for Provider {
     const PAGE_SIZE: usize = 4096;
 
     fn alloc_dma(size: usize) -> (usize, usize) {
-        let layout = Layout::from_size_align(size, Self::PAGE_SIZE).unwrap();
-        let paddr = unsafe { alloc(layout) as usize };
-        (paddr, paddr)
+        dma_alloc(size).expect("e1000: out of DMA memory")
     }
 
     fn dealloc_dma(vaddr: usize, size: usize) {
-        let layout = Layout::from_size_align(size, Self::PAGE_SIZE).unwrap();
-        unsafe {
-            dealloc(vaddr as *mut u8, layout);
-        }
+        dma_dealloc(vaddr, size);
     }
 }
 
 pub static E1000_DRIVER: Lazy<Mutex<Option<E1000<Provider>>>> = Lazy::new(|| Mutex::new(None));
 
-pub fn init(header: usize, size: usize) {
+pub static PCI_DRIVER: PciDriver = PciDriver {
+    name: "e1000",
+    ids: &[(0x8086, 0x100e)],
+    probe,
+};
+
+fn probe(device: &PciDevice) -> bool {
+    let (Some(bar), Some(irq)) = (device.bars[0], device.irq) else {
+        return false;
+    };
+    device.enable();
+    init(bar.addr, bar.size, irq);
+    true
+}
+
+pub fn init(header: usize, size: usize, irq: u32) {
+    E1000_IRQ.store(irq, Ordering::Relaxed);
     let e1000 = E1000::new(
         header,
         size,
@@ -47,6 +54,13 @@ pub fn init(header: usize, size: usize) {
 
     let mut lock = E1000_DRIVER.lock();
     *lock = Some(e1000);
+    drop(lock);
+    net::register(net::Nic::E1000);
+}
+
+#[inline]
+pub fn irq() -> u32 {
+    E1000_IRQ.load(Ordering::Relaxed)
 }
 
 #[inline]
@@ -92,6 +106,3 @@ pub fn send(buf: &[u8]) {
         .send(buf);
 }
 
-pub fn handle_interrupt() {
-    ASYNC_WAIT_WAKER.wake();
-}
@@ -0,0 +1,115 @@
+#![allow(unused)]
+
+//! ELF64 解析, 只支持加载 RISC-V 小端可执行文件需要的部分
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_RISCV: u16 = 0xf3;
+/// ELF64 程序头的大小
+const PHDR_SIZE: usize = 56;
+
+pub const PT_LOAD: u32 = 1;
+
+/// 段权限
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+pub const PF_R: u32 = 4;
+
+/// 程序头
+#[derive(Clone, Copy, Debug)]
+pub struct ProgramHeader {
+    pub p_type: u32,
+    pub p_flags: u32,
+    pub p_offset: usize,
+    pub p_vaddr: usize,
+    pub p_filesz: usize,
+    pub p_memsz: usize,
+}
+
+pub struct Elf<'a> {
+    data: &'a [u8],
+    /// 入口地址
+    pub entry: usize,
+    phoff: usize,
+    phentsize: usize,
+    phnum: usize,
+}
+
+#[inline]
+fn read_u16(data: &[u8], off: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(off..off.checked_add(2)?)?.try_into().ok()?,
+    ))
+}
+
+#[inline]
+fn read_u32(data: &[u8], off: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(off..off.checked_add(4)?)?.try_into().ok()?,
+    ))
+}
+
+#[inline]
+fn read_u64(data: &[u8], off: usize) -> Option<usize> {
+    Some(u64::from_le_bytes(data.get(off..off.checked_add(8)?)?.try_into().ok()?) as usize)
+}
+
+impl<'a> Elf<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, &'static str> {
+        if data.get(0..4) != Some(&ELF_MAGIC[..]) {
+            return Err("not an ELF file");
+        }
+        if data.get(4) != Some(&ELFCLASS64) || data.get(5) != Some(&ELFDATA2LSB) {
+            return Err("not a little-endian ELF64 file");
+        }
+        if read_u16(data, 16) != Some(ET_EXEC) {
+            return Err("not an executable");
+        }
+        if read_u16(data, 18) != Some(EM_RISCV) {
+            return Err("not a RISC-V executable");
+        }
+
+        let elf = Self {
+            data,
+            entry: read_u64(data, 24).ok_or("truncated header")?,
+            phoff: read_u64(data, 32).ok_or("truncated header")?,
+            phentsize: read_u16(data, 54).ok_or("truncated header")? as usize,
+            phnum: read_u16(data, 56).ok_or("truncated header")? as usize,
+        };
+        if elf.phentsize < PHDR_SIZE {
+            return Err("bad program header size");
+        }
+        if elf.phnum > 0 && elf.program_header(elf.phnum - 1).is_none() {
+            return Err("truncated program headers");
+        }
+        Ok(elf)
+    }
+
+    fn program_header(&self, i: usize) -> Option<ProgramHeader> {
+        let off = self.phoff.checked_add(i.checked_mul(self.phentsize)?)?;
+        let data = self.data;
+        Some(ProgramHeader {
+            p_type: read_u32(data, off)?,
+            p_flags: read_u32(data, off.checked_add(4)?)?,
+            p_offset: read_u64(data, off.checked_add(8)?)?,
+            p_vaddr: read_u64(data, off.checked_add(16)?)?,
+            p_filesz: read_u64(data, off.checked_add(32)?)?,
+            p_memsz: read_u64(data, off.checked_add(40)?)?,
+        })
+    }
+
+    /// 所有 PT_LOAD 段
+    pub fn loads(&self) -> impl Iterator<Item = ProgramHeader> + '_ {
+        (0..self.phnum)
+            .filter_map(|i| self.program_header(i))
+            .filter(|ph| ph.p_type == PT_LOAD)
+    }
+
+    /// 段在文件中的内容
+    pub fn segment_data(&self, ph: &ProgramHeader) -> Option<&'a [u8]> {
+        self.data
+            .get(ph.p_offset..ph.p_offset.checked_add(ph.p_filesz)?)
+    }
+}
@@ -0,0 +1,188 @@
+#![allow(unused)]
+
+//! 物理页帧分配器, 管理内核堆之后的物理内存, 供用户进程和 DMA 使用
+
+extern crate alloc;
+
+use crate::{
+    trap::{pop_on, push_off},
+    vm::PAGE_SIZE,
+};
+use alloc::{vec, vec::Vec};
+use spin::{Mutex, Once};
+use stdio::log;
+
+/// 位图分配器, 支持连续多页分配
+struct BitmapAllocator {
+    /// 第一个页帧的物理地址
+    base: usize,
+    /// 页帧数量
+    total: usize,
+    free: usize,
+    /// 置位表示已分配
+    bits: Vec<u64>,
+    /// 下一次查找的起点
+    next: usize,
+}
+
+impl BitmapAllocator {
+    fn new(base: usize, total: usize) -> Self {
+        Self {
+            base,
+            total,
+            free: total,
+            bits: vec![0; total.div_ceil(64)],
+            next: 0,
+        }
+    }
+
+    #[inline]
+    fn test(&self, i: usize) -> bool {
+        self.bits[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    #[inline]
+    fn set(&mut self, i: usize, used: bool) {
+        if used {
+            self.bits[i / 64] |= 1 << (i % 64);
+        } else {
+            self.bits[i / 64] &= !(1 << (i % 64));
+        }
+    }
+
+    /// 从 next 开始首次适应, 到末尾后回绕一次
+    fn alloc(&mut self, count: usize) -> Option<usize> {
+        if count == 0 || count > self.free {
+            return None;
+        }
+        let start = self.find(self.next, self.total, count).or_else(|| {
+            let end = (self.next + count - 1).min(self.total);
+            self.find(0, end, count)
+        })?;
+        for i in start..start + count {
+            self.set(i, true);
+        }
+        self.free -= count;
+        self.next = start + count;
+        Some(self.base + start * PAGE_SIZE)
+    }
+
+    /// 在 [from, to) 中查找 count 个连续空闲页帧
+    fn find(&self, from: usize, to: usize, count: usize) -> Option<usize> {
+        let mut run = 0;
+        let mut i = from;
+        while i < to {
+            // 整字已满时跳过
+            if i % 64 == 0 && self.bits[i / 64] == u64::MAX {
+                run = 0;
+                i += 64;
+                continue;
+            }
+            if self.test(i) {
+                run = 0;
+            } else {
+                run += 1;
+                if run == count {
+                    return Some(i + 1 - count);
+                }
+            }
+            i += 1;
+        }
+        None
+    }
+
+    fn dealloc(&mut self, paddr: usize, count: usize) {
+        let start = (paddr - self.base) / PAGE_SIZE;
+        assert!(
+            start + count <= self.total,
+            "dealloc {paddr:#x}: not a frame"
+        );
+        for i in start..start + count {
+            assert!(
+                self.test(i),
+                "dealloc {:#x}: double free",
+                self.base + i * PAGE_SIZE
+            );
+            self.set(i, false);
+        }
+        self.free += count;
+    }
+}
+
+static FRAMES: Once<Mutex<BitmapAllocator>> = Once::new();
+
+/// 管理 [start, end) 范围的物理内存, 需要在堆初始化之后调用
+pub fn init(start: usize, end: usize) {
+    let start = start.next_multiple_of(PAGE_SIZE);
+    let end = end & !(PAGE_SIZE - 1);
+    let total = end.saturating_sub(start) / PAGE_SIZE;
+    FRAMES.call_once(|| Mutex::new(BitmapAllocator::new(start, total)));
+    log::info!("frames: [{start:#x}, {end:#x}), {total} pages");
+}
+
+#[inline]
+fn with_frames<T>(f: impl FnOnce(&mut BitmapAllocator) -> T) -> T {
+    // 线程持锁时不能被调度器打断
+    let sstatus = push_off();
+    let ret = f(&mut FRAMES.wait().lock());
+    pop_on(sstatus);
+    ret
+}
+
+/// 分配 count 个连续物理页帧, 返回首地址, 内容未清零
+pub fn alloc_frames(count: usize) -> Option<usize> {
+    with_frames(|frames| frames.alloc(count))
+}
+
+/// 释放 alloc_frames 分配的页帧
+pub fn dealloc_frames(paddr: usize, count: usize) {
+    with_frames(|frames| frames.dealloc(paddr, count));
+}
+
+/// (空闲页帧, 总页帧)
+pub fn stats() -> (usize, usize) {
+    with_frames(|frames| (frames.free, frames.total))
+}
+
+/// 物理地址在内核地址空间中的虚拟地址, 目前为恒等映射
+#[inline]
+pub fn phys_to_virt(paddr: usize) -> usize {
+    paddr
+}
+
+/// phys_to_virt 的逆映射
+#[inline]
+pub fn virt_to_phys(vaddr: usize) -> usize {
+    vaddr
+}
+
+/// 单个清零的物理页帧, drop 时释放
+pub struct FrameTracker(pub usize);
+
+impl FrameTracker {
+    pub fn new() -> Option<Self> {
+        let paddr = alloc_frames(1)?;
+        unsafe { core::ptr::write_bytes(phys_to_virt(paddr) as *mut u8, 0, PAGE_SIZE) };
+        Some(Self(paddr))
+    }
+}
+
+impl Drop for FrameTracker {
+    fn drop(&mut self) {
+        dealloc_frames(self.0, 1);
+    }
+}
+
+/// 分配清零的物理连续 DMA 内存, 返回 (vaddr, paddr)
+pub fn dma_alloc(size: usize) -> Option<(usize, usize)> {
+    let count = size.div_ceil(PAGE_SIZE);
+    let paddr = alloc_frames(count)?;
+    let vaddr = phys_to_virt(paddr);
+    unsafe { core::ptr::write_bytes(vaddr as *mut u8, 0, count * PAGE_SIZE) };
+    Some((vaddr, paddr))
+}
+
+/// 释放 dma_alloc 分配的内存
+pub fn dma_dealloc(vaddr: usize, size: usize) {
+    dealloc_frames(virt_to_phys(vaddr), size.div_ceil(PAGE_SIZE));
+}
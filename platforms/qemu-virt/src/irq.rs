@@ -0,0 +1,89 @@
+#![allow(unused)]
+
+//! 外部中断分发表: 驱动按中断号登记处理函数, 协程按中断号等待
+
+extern crate alloc;
+
+use crate::trap::{pop_on, push_off};
+use alloc::vec::Vec;
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    task::Waker,
+};
+use spin::Mutex;
+use stdio::log;
+
+/// PLIC 中断源数量上限
+pub const MAX_IRQ: usize = 128;
+
+/// 在调度器中调用的中断处理函数, 负责应答设备
+pub type Handler = fn();
+
+static HANDLERS: Mutex<[Option<Handler>; MAX_IRQ]> = Mutex::new([None; MAX_IRQ]);
+
+/// 每个中断号到来的次数, 等待者据此判断是否有新的中断
+static SEQ: [AtomicUsize; MAX_IRQ] = [const { AtomicUsize::new(0) }; MAX_IRQ];
+
+/// 每个中断号上的等待者, 中断到来时全部唤醒
+static WAITERS: Mutex<[Vec<Waker>; MAX_IRQ]> = Mutex::new([const { Vec::new() }; MAX_IRQ]);
+
+#[inline]
+fn valid(irq: u32) -> bool {
+    (1..MAX_IRQ as u32).contains(&irq)
+}
+
+/// 登记中断处理函数, 每个中断号只有一个, 后登记的覆盖之前的
+pub fn register_handler(irq: u32, handler: Handler) {
+    if !valid(irq) {
+        log::warn!("irq {irq}: out of range");
+        return;
+    }
+    let sstatus = push_off();
+    HANDLERS.lock()[irq as usize] = Some(handler);
+    pop_on(sstatus);
+}
+
+/// 中断号为 irq 的中断已到来的次数
+pub fn seq(irq: u32) -> usize {
+    if valid(irq) {
+        SEQ[irq as usize].load(Ordering::Acquire)
+    } else {
+        0
+    }
+}
+
+/// 登记 waker, 中断次数超过 seq 时唤醒; 已经超过时立即唤醒
+pub fn register_waker(irq: u32, seq: usize, waker: &Waker) {
+    if !valid(irq) {
+        // 不会到来的中断, 立即唤醒以免永远等待
+        waker.wake_by_ref();
+        return;
+    }
+    let sstatus = push_off();
+    let mut waiters = WAITERS.lock();
+    // 在锁内检查, dispatch 先增加计数再取出等待者, 不会错过
+    if SEQ[irq as usize].load(Ordering::Acquire) != seq {
+        waker.wake_by_ref();
+    } else {
+        let list = &mut waiters[irq as usize];
+        if !list.iter().any(|w| w.will_wake(waker)) {
+            list.push(waker.clone());
+        }
+    }
+    drop(waiters);
+    pop_on(sstatus);
+}
+
+/// 在调度器中分发 PLIC claim 到的中断
+pub fn dispatch(irq: u32) {
+    if !valid(irq) {
+        return;
+    }
+    let handler = HANDLERS.lock()[irq as usize];
+    if let Some(handler) = handler {
+        handler();
+    }
+    SEQ[irq as usize].fetch_add(1, Ordering::Release);
+    let waiters = core::mem::take(&mut WAITERS.lock()[irq as usize]);
+    waiters.into_iter().for_each(Waker::wake);
+}
@@ -7,20 +7,33 @@
 #![feature(alloc_error_handler)]
 
 mod async_executor;
+mod board;
 mod consts;
 mod e1000;
+mod elf;
+mod frame;
+mod irq;
 mod mm;
+mod net;
 mod pci;
 mod plic;
+mod process;
 mod syscall;
 mod tasks;
 mod thread;
 mod timer;
+mod trace;
 mod trap;
+mod uart;
 mod virt;
+mod virtio;
+mod virtio_blk;
+mod virtio_net;
+mod vm;
 
 extern crate alloc;
 extern crate timer as crate_timer;
+extern crate trace as crate_trace;
 
 use executor::async_yield;
 use qemu_virt_ld as linker;
@@ -33,14 +46,15 @@ use thread::*;
 use uart_16550::MmioSerialPort;
 
 pub use consts::*;
-pub use platform::Platform;
+pub use platform::{AllocRecord, Platform, TaskInfo, TaskState};
 use virt::Virt;
 pub use virt::Virt as PlatformImpl;
 
 use crate::{
-    plic::{plic_claim, plic_complete, E1000_IRQ},
+    plic::{plic_claim, plic_complete},
     tasks::{add_task_to_queue, add_task_transient, get_task_from_queue},
     timer::check_timer,
+    trace::SchedEvent,
 };
 
 #[linkage = "weak"]
@@ -51,24 +65,67 @@ fn obj_main() {
 
 linker::boot0!(rust_main; stack = 4096 * 12);
 
-extern "C" fn rust_main() -> ! {
+/// a1 为 OpenSBI 传入的设备树地址
+extern "C" fn rust_main(_hartid: usize, dtb: usize) -> ! {
     let layout = linker::KernelLayout::locate();
     unsafe {
         layout.zero_bss();
     }
-    let (heap_base, _heap_size) = Virt::heap();
-    mm::init_heap(heap_base, MEMORY_SIZE);
+    // 堆的大小取决于物理内存, 此时还不能完整解析设备树
+    let heap_base = layout.end();
+    let memory = board::memory(dtb).unwrap_or(board::DEFAULT_MEMORY);
+    // 内存不在内核之后时使用默认布局, 日志初始化后再警告
+    let (ram_end, ram_size, bad_memory) = match memory.end.checked_sub(heap_base) {
+        Some(size) if size > 0 => (memory.end, size, None),
+        _ => {
+            let end = board::DEFAULT_MEMORY.end;
+            (end, end - heap_base, Some(memory))
+        }
+    };
+    let mut heap_end = heap_base + ram_size / HEAP_FRACTION;
+    if (heap_base..heap_end).contains(&dtb) {
+        heap_end = dtb;
+    }
+    let heap_size = (heap_end - heap_base) & !(vm::PAGE_SIZE - 1);
+    mm::init_heap(heap_base, heap_size);
+
+    // 设备树, 需要堆
+    let dtb_result = board::init(dtb);
+    let info = board::board();
 
-    virt::init(unsafe { MmioSerialPort::new(0x1000_0000) });
+    virt::init(unsafe { MmioSerialPort::new(info.uart) });
+    uart::init();
 
     // stdio
-    stdio::set_log_level(option_env!("LOG"));
     stdio::init(&virt::Stdio);
+    stdio::set_log_level(option_env!("LOG"));
+
+    if let Err(err) = dtb_result {
+        log::warn!("bad dtb at {dtb:#x}: {err:?}, use default layout");
+    }
+    if let Some(memory) = bad_memory {
+        log::warn!("memory {memory:#x?} ends before the kernel, use default memory");
+    }
+    board::print();
+
+    // 物理页帧, qemu 把设备树放在内存末尾, 不能覆盖
+    let frame_end = match info.dtb.start {
+        start if (heap_base..ram_end).contains(&start) => start,
+        _ => ram_end,
+    };
+    frame::init(heap_base + heap_size, frame_end);
+
+    // 分页
+    vm::init(heap_base, ram_size);
+    process::init_portal();
 
     crate_timer::init(&virt::TimeProvider);
+    crate_trace::init(&virt::Tracer);
     executor::init(&virt::Executor);
 
+    pci::register_driver(&e1000::PCI_DRIVER);
     pci::pci_init();
+    virtio::virtio_init();
     // 中断
     plic::plic_init();
     plic::plic_init_hart();
@@ -82,8 +139,9 @@ extern "C" fn rust_main() -> ! {
             }
         },
         true,
+        DEFAULT_PRIORITY,
     );
-    Virt::spawn(async { obj_main() }, false);
+    Virt::spawn(async { obj_main() }, false, DEFAULT_PRIORITY);
 
     let mut t = TaskControlBlock::ZERO;
     t.init(schedule as usize);
@@ -94,11 +152,15 @@ extern "C" fn rust_main() -> ! {
 }
 
 #[inline]
-fn get_slice(io: bool) -> u64 {
-    match io {
-        true => 12500 * 1,
-        _ => 12500 * 1,
-    }
+fn get_slice(io: bool, priority: usize) -> u64 {
+    // 1ms
+    let tick = (board::timebase() / MILLI_PER_SEC) as u64;
+    let slice = match io {
+        true => tick * 1,
+        _ => tick * 1,
+    };
+    // 优先级越高时间片越长，DEFAULT_PRIORITY 对应基础时间片
+    slice * (MAX_PRIORITY + 1 - priority) as u64 / (MAX_PRIORITY + 1 - DEFAULT_PRIORITY) as u64
 }
 
 extern "C" fn schedule() -> ! {
@@ -110,27 +172,42 @@ extern "C" fn schedule() -> ! {
     }
 
     loop {
-        let task = get_task_from_queue();
-        let mut task = task.expect("no task, Shutdown");
+        let mut task = match get_task_from_queue() {
+            Some(task) => task,
+            None => {
+                trace::dump();
+                panic!("no task, Shutdown");
+            }
+        };
 
         let ticks = task.ticks(); // 用于task在给定时间片内是否切换协程
 
         // 计算密集型任务执行线程优先级更高、但时间片更少
         if task.status() == TaskStatus::Blocking {
             task.set_status(TaskStatus::Running);
-            set_timer(Virt::rdtime() as u64 + get_slice(task.io));
+            task.stats.slices += 1;
+            trace::record(task.tid, SchedEvent::Run);
+            set_timer(Virt::rdtime() as u64 + get_slice(task.io, task.priority));
         }
+        let begin = Virt::rdtime();
         task.run();
+        task.stats.run_time += Virt::rdtime() - begin;
+        task.check_stack();
+        task.stats.polls = task.ticks();
 
         use scause::{Exception, Interrupt, Trap};
-        match scause::read().cause() {
+        let cause = scause::read().cause();
+        task.in_syscall = matches!(cause, Trap::Exception(Exception::UserEnvCall));
+        match cause {
             Trap::Interrupt(Interrupt::SupervisorTimer) => {
                 set_timer(u64::MAX);
                 check_timer();
 
                 task.set_status(TaskStatus::Blocking);
+                task.stats.preempted += 1;
+                trace::record(task.tid, SchedEvent::Preempt);
 
-                let new_ticks = task.ticks();
+                let new_ticks = task.stats.polls;
                 if new_ticks == ticks {
                     if task.io {
                         // steal coroutine from task to new IO task
@@ -149,12 +226,8 @@ extern "C" fn schedule() -> ! {
             }
             Trap::Interrupt(Interrupt::SupervisorExternal) => {
                 if let Some(irq) = plic_claim() {
-                    match irq as usize {
-                        E1000_IRQ => {
-                            e1000::handle_interrupt();
-                        }
-                        _ => {}
-                    }
+                    trace::record(task.tid, SchedEvent::Irq(irq));
+                    irq::dispatch(irq);
                     plic_complete(irq);
                 }
                 add_task_transient(task);
@@ -169,6 +242,19 @@ extern "C" fn schedule() -> ! {
                     }
                 }
             }
+            Trap::Exception(
+                Exception::InstructionPageFault
+                | Exception::LoadPageFault
+                | Exception::StorePageFault,
+            ) => {
+                panic!(
+                    "{:?} in thread {}: sepc {:x}, stval {:x}",
+                    scause::read().cause(),
+                    task.tid,
+                    sepc::read(),
+                    stval::read()
+                );
+            }
             _ => {
                 log::info!(
                     "{:#?}, spec {:x}, stval {:x}",
@@ -1,21 +1,293 @@
 #![allow(dead_code)]
 #![allow(unused_imports)]
 
-use core::alloc::{Allocator, GlobalAlloc, Layout};
+use core::{
+    alloc::{Allocator, GlobalAlloc, Layout},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed},
+};
 use good_memory_allocator::SpinLockedAllocator;
+use platform::{AllocRecord, HeapStats, ALLOC_SITE_DEPTH};
+use spin::Mutex;
+use stdio::println;
 
-use crate::trap::{pop_on, push_off};
+use crate::{
+    tasks::current_tid,
+    trap::{cpuid, pop_on, push_off},
+};
 
 pub(crate) static HEAP_ALLOCATOR: SpinLockedAllocator = SpinLockedAllocator::empty();
 
+/// 堆范围, 用于统计和校验栈帧
+static HEAP_BASE: AtomicUsize = AtomicUsize::new(0);
+static HEAP_SIZE: AtomicUsize = AtomicUsize::new(0);
+
 /// initiate heap allocator used by dispatcher
 pub(crate) fn init_heap(_heap_base: usize, _heap_size: usize) {
+    HEAP_BASE.store(_heap_base, Relaxed);
+    HEAP_SIZE.store(_heap_size, Relaxed);
     unsafe {
         HEAP_ALLOCATOR.init(_heap_base, _heap_size);
     }
 }
 
-struct GlobalAllocator; 
+/// 堆的起始地址和大小
+pub fn heap() -> (usize, usize) {
+    (HEAP_BASE.load(Relaxed), HEAP_SIZE.load(Relaxed))
+}
+
+// 计数器只在关闭中断时修改
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+static DEALLOCS: AtomicUsize = AtomicUsize::new(0);
+static FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+/// 记录分配调用点的槽位数量, 写满后新的分配不再记录
+const TRACK_SLOTS: usize = 4096;
+
+static TRACKING: AtomicBool = AtomicBool::new(false);
+static TRACKER: Mutex<Tracker> = Mutex::new(Tracker::new());
+
+/// 以指针为键的开放寻址表
+struct Tracker {
+    records: [AllocRecord; TRACK_SLOTS],
+    len: usize,
+    /// 表满而未记录的分配
+    dropped: usize,
+}
+
+impl Tracker {
+    /// 空槽位与删除标记, 分配器不会返回这两个地址
+    const EMPTY: usize = 0;
+    const TOMB: usize = 1;
+
+    const fn new() -> Self {
+        Self {
+            records: [AllocRecord {
+                ptr: Self::EMPTY,
+                size: 0,
+                tid: 0,
+                sites: [0; ALLOC_SITE_DEPTH],
+            }; TRACK_SLOTS],
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    #[inline]
+    fn probe(ptr: usize) -> impl Iterator<Item = usize> {
+        let start = (ptr >> 4) % TRACK_SLOTS;
+        (0..TRACK_SLOTS).map(move |i| (start + i) % TRACK_SLOTS)
+    }
+
+    fn insert(&mut self, record: AllocRecord) {
+        for i in Self::probe(record.ptr) {
+            if matches!(self.records[i].ptr, Self::EMPTY | Self::TOMB) {
+                self.records[i] = record;
+                self.len += 1;
+                return;
+            }
+        }
+        self.dropped += 1;
+    }
+
+    fn remove(&mut self, ptr: usize) {
+        for i in Self::probe(ptr) {
+            match self.records[i].ptr {
+                Self::EMPTY => return,
+                p if p == ptr => {
+                    self.records[i].ptr = Self::TOMB;
+                    self.len -= 1;
+                    return;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        for record in self.records.iter_mut() {
+            record.ptr = Self::EMPTY;
+        }
+        self.len = 0;
+        self.dropped = 0;
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &AllocRecord> {
+        self.records.iter().filter(|r| r.ptr > Self::TOMB)
+    }
+}
+
+/// 沿帧指针回溯, 需要 force-frame-pointers
+///
+/// 跳过 skip 层后把返回地址写入 buf, 返回写入的层数
+#[inline(always)]
+pub fn backtrace(skip: usize, buf: &mut [usize]) -> usize {
+    let mut fp: usize;
+    unsafe { core::arch::asm!("mv {}, s0", out(reg) fp) };
+    // 栈位于内核镜像 (启动栈) 或堆 (线程栈) 中
+    let low = qemu_virt_ld::KernelLayout::locate().start();
+    let high = HEAP_BASE.load(Relaxed) + HEAP_SIZE.load(Relaxed);
+    let mut count = 0;
+    for depth in 0..skip + buf.len() {
+        if fp < low + 16 || fp > high || fp % 8 != 0 {
+            break;
+        }
+        let ra = unsafe { *((fp - 8) as *const usize) };
+        if depth >= skip {
+            buf[depth - skip] = ra;
+            count += 1;
+        }
+        fp = unsafe { *((fp - 16) as *const usize) };
+    }
+    count
+}
+
+#[inline(always)]
+fn call_sites() -> [usize; ALLOC_SITE_DEPTH] {
+    // 跳过 on_alloc, GlobalAlloc::alloc 和 __rust_alloc 等转发函数
+    const SKIP: usize = 3;
+    let mut sites = [0; ALLOC_SITE_DEPTH];
+    backtrace(SKIP, &mut sites);
+    sites
+}
+
+/// 更新计数器, 调用者需要关闭中断
+fn on_alloc(ptr: *mut u8, size: usize) {
+    if ptr.is_null() {
+        FAILURES.fetch_add(1, Relaxed);
+        return;
+    }
+    ALLOCS.fetch_add(1, Relaxed);
+    let used = IN_USE.fetch_add(size, Relaxed) + size;
+    PEAK.fetch_max(used, Relaxed);
+    if TRACKING.load(Relaxed) {
+        TRACKER.lock().insert(AllocRecord {
+            ptr: ptr as usize,
+            size,
+            tid: current_tid().unwrap_or(usize::MAX),
+            sites: call_sites(),
+        });
+    }
+}
+
+fn on_dealloc(ptr: *mut u8, size: usize) {
+    DEALLOCS.fetch_add(1, Relaxed);
+    IN_USE.fetch_sub(size, Relaxed);
+    if TRACKING.load(Relaxed) {
+        TRACKER.lock().remove(ptr as usize);
+    }
+}
+
+/// realloc 成功后更新计数器, 视为一次分配
+fn on_realloc(old: *mut u8, old_size: usize, new: *mut u8, new_size: usize) {
+    // 失败时原内存保持不变
+    if !new.is_null() {
+        on_dealloc(old, old_size);
+        DEALLOCS.fetch_sub(1, Relaxed);
+    }
+    on_alloc(new, new_size);
+}
+
+/// 小对象大小分级, 不超过 SLAB_MAX 的分配从 slab 缓存中取
+const SIZE_CLASSES: usize = 9;
+const MIN_CLASS_SHIFT: u32 = 4;
+const SLAB_MAX: usize = 1 << (MIN_CLASS_SHIFT as usize + SIZE_CLASSES - 1);
+/// 每次从堆中取出的 slab 大小, 按页对齐, 因此对象按自身大小对齐
+const SLAB_SIZE: usize = 32 << 10;
+const SLAB_ALIGN: usize = 4096;
+
+/// 编译时设置 NO_SLAB 环境变量关闭 slab, 所有分配直接走堆, 用于对比分配性能
+///
+/// NO_SLAB 为空或 0 时仍使用 slab
+const SLAB_DISABLED: bool = match option_env!("NO_SLAB") {
+    Some(value) => !matches!(value.as_bytes(), b"" | b"0"),
+    None => false,
+};
+
+/// 目前只有一个 CPU
+const MAX_CPUS: usize = 1;
+
+/// 对齐后不超过 SLAB_MAX 的分配所属的大小级别
+#[inline]
+fn size_class(layout: &Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align());
+    if SLAB_DISABLED || size > SLAB_MAX {
+        return None;
+    }
+    let shift = size
+        .next_power_of_two()
+        .trailing_zeros()
+        .max(MIN_CLASS_SHIFT);
+    Some((shift - MIN_CLASS_SHIFT) as usize)
+}
+
+#[inline]
+const fn class_size(class: usize) -> usize {
+    1 << (MIN_CLASS_SHIFT as usize + class)
+}
+
+/// 单个大小级别的空闲链表, 空闲对象的第一个字保存下一个对象的地址
+#[derive(Clone, Copy)]
+struct SizeClass {
+    free: usize,
+    /// 链表中的空闲对象数量
+    cached: usize,
+    /// 从堆中取出的 slab 数量, slab 不归还给堆
+    slabs: usize,
+}
+
+impl SizeClass {
+    const EMPTY: Self = Self {
+        free: 0,
+        cached: 0,
+        slabs: 0,
+    };
+
+    unsafe fn pop(&mut self, size: usize) -> *mut u8 {
+        if self.free == 0 {
+            self.refill(size);
+        }
+        let obj = self.free;
+        if obj != 0 {
+            self.free = *(obj as *const usize);
+            self.cached -= 1;
+        }
+        obj as *mut u8
+    }
+
+    unsafe fn push(&mut self, obj: *mut u8) {
+        *(obj as *mut usize) = self.free;
+        self.free = obj as usize;
+        self.cached += 1;
+    }
+
+    unsafe fn refill(&mut self, size: usize) {
+        let layout = Layout::from_size_align_unchecked(SLAB_SIZE, SLAB_ALIGN);
+        let slab = HEAP_ALLOCATOR.alloc(layout);
+        if slab.is_null() {
+            return;
+        }
+        self.slabs += 1;
+        // 倒序压入, 使先分配的对象地址较低
+        for offset in (0..SLAB_SIZE).step_by(size).rev() {
+            self.push(slab.add(offset));
+        }
+    }
+}
+
+/// 每个 CPU 一份的 slab 缓存, 只在关闭中断时访问, 锁不会竞争
+struct SlabCache([SizeClass; SIZE_CLASSES]);
+
+static SLABS: [Mutex<SlabCache>; MAX_CPUS] =
+    [const { Mutex::new(SlabCache([SizeClass::EMPTY; SIZE_CLASSES])) }; MAX_CPUS];
+
+#[inline]
+fn slab_cache() -> spin::MutexGuard<'static, SlabCache> {
+    SLABS[cpuid()].lock()
+}
+
+struct GlobalAllocator;
 /// global allocator
 #[cfg(not(feature = "std"))]
 #[global_allocator]
@@ -24,30 +296,155 @@ static GLOBAL_ALLOCATOR_IMPL: GlobalAllocator = GlobalAllocator {};
 unsafe impl GlobalAlloc for GlobalAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let sstatus = push_off();
-        let ret = HEAP_ALLOCATOR.alloc(layout);
+        let ret = match size_class(&layout) {
+            Some(class) => slab_cache().0[class].pop(class_size(class)),
+            None => HEAP_ALLOCATOR.alloc(layout),
+        };
+        on_alloc(ret, layout.size());
         pop_on(sstatus);
         ret
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         let sstatus = push_off();
-        let ret = HEAP_ALLOCATOR.dealloc(ptr, layout);
+        match size_class(&layout) {
+            Some(class) => slab_cache().0[class].push(ptr),
+            None => HEAP_ALLOCATOR.dealloc(ptr, layout),
+        }
+        on_dealloc(ptr, layout.size());
         pop_on(sstatus);
-        ret
     }
 
     unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
-        let sstatus = push_off();
-        let ret = HEAP_ALLOCATOR.realloc(ptr, layout, new_size);
-        pop_on(sstatus);
-        ret
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        match (size_class(&layout), size_class(&new_layout)) {
+            (None, None) => {
+                let sstatus = push_off();
+                let ret = HEAP_ALLOCATOR.realloc(ptr, layout, new_size);
+                on_realloc(ptr, layout.size(), ret, new_size);
+                pop_on(sstatus);
+                ret
+            }
+            // 同一级别内无需移动
+            (Some(old), Some(new)) if old == new => {
+                let sstatus = push_off();
+                on_realloc(ptr, layout.size(), ptr, new_size);
+                pop_on(sstatus);
+                ptr
+            }
+            _ => {
+                let ret = self.alloc(new_layout);
+                if !ret.is_null() {
+                    core::ptr::copy_nonoverlapping(ptr, ret, layout.size().min(new_size));
+                    self.dealloc(ptr, layout);
+                }
+                ret
+            }
+        }
+    }
+}
+
+/// 二分查找能分配的最大块, 调用者需要关闭中断
+fn largest_free_block() -> usize {
+    let (mut lo, mut hi) = (0, HEAP_SIZE.load(Relaxed) / 8);
+    while lo < hi {
+        let mid = (lo + hi + 1) / 2;
+        let layout = Layout::from_size_align(mid * 8, 8).unwrap();
+        let ptr = unsafe { HEAP_ALLOCATOR.alloc(layout) };
+        if ptr.is_null() {
+            hi = mid - 1;
+        } else {
+            unsafe { HEAP_ALLOCATOR.dealloc(ptr, layout) };
+            lo = mid;
+        }
+    }
+    lo * 8
+}
+
+/// 堆使用统计, largest_free 需要试探分配, 开销较大
+pub fn heap_stats() -> HeapStats {
+    let sstatus = push_off();
+    let stats = HeapStats {
+        total: HEAP_SIZE.load(Relaxed),
+        in_use: IN_USE.load(Relaxed),
+        peak: PEAK.load(Relaxed),
+        allocs: ALLOCS.load(Relaxed),
+        deallocs: DEALLOCS.load(Relaxed),
+        failures: FAILURES.load(Relaxed),
+        largest_free: largest_free_block(),
+    };
+    pop_on(sstatus);
+    stats
+}
+
+/// 开关分配记录, 打开时清空已有记录
+pub fn heap_track(enable: bool) {
+    let sstatus = push_off();
+    if enable {
+        TRACKER.lock().clear();
+    }
+    TRACKING.store(enable, Relaxed);
+    pop_on(sstatus);
+}
+
+/// 将仍未释放的分配记录写入 buf, 返回记录总数
+pub fn heap_records(buf: &mut [AllocRecord]) -> usize {
+    let sstatus = push_off();
+    let tracker = TRACKER.lock();
+    for (slot, record) in buf.iter_mut().zip(tracker.iter()) {
+        *slot = *record;
+    }
+    let len = tracker.len;
+    drop(tracker);
+    pop_on(sstatus);
+    len
+}
+
+/// 打印统计和未释放的分配记录, 不分配内存, 可在 OOM 时调用
+pub fn heap_dump() {
+    let sstatus = push_off();
+    let stats = heap_stats();
+    println!(
+        "==== heap: {} / {} bytes in use, peak {}, {} allocs, {} deallocs, {} failures, largest free {} ====",
+        stats.in_use,
+        stats.total,
+        stats.peak,
+        stats.allocs,
+        stats.deallocs,
+        stats.failures,
+        stats.largest_free
+    );
+    for (class, slab) in slab_cache().0.iter().enumerate() {
+        if slab.slabs > 0 {
+            println!(
+                "  slab {:>5}: {:>6} free objects, {} slabs",
+                class_size(class),
+                slab.cached,
+                slab.slabs
+            );
+        }
+    }
+    if TRACKING.load(Relaxed) {
+        let tracker = TRACKER.lock();
+        println!(
+            "{} live allocations tracked, {} dropped",
+            tracker.len, tracker.dropped
+        );
+        for record in tracker.iter() {
+            println!(
+                "  tid {:>4} {:#x} {:>8} bytes, sites {:x?}",
+                record.tid as isize, record.ptr, record.size, record.sites
+            );
+        }
     }
+    pop_on(sstatus);
 }
 
 #[cfg(not(feature = "std"))]
 #[alloc_error_handler]
 /// panic when heap allocation error occurs
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
+    heap_dump();
     panic!("Heap allocation error, layout = {:?}", layout);
 }
 
@@ -0,0 +1,60 @@
+#![allow(unused)]
+
+//! 网卡选择, 第一个初始化成功的网卡作为 net_* 的后端
+
+use crate::{e1000, virtio_net};
+use spin::Once;
+use stdio::log;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nic {
+    E1000,
+    VirtIO,
+}
+
+static NIC: Once<Nic> = Once::new();
+
+/// 登记网卡, 已有网卡时忽略
+pub fn register(nic: Nic) {
+    let active = *NIC.call_once(|| nic);
+    if active != nic {
+        log::warn!("net: {nic:?} ignored, using {active:?}");
+    }
+}
+
+#[inline]
+pub fn nic() -> Option<Nic> {
+    NIC.get().copied()
+}
+
+pub fn recv(buf: &mut [u8]) -> usize {
+    match nic() {
+        Some(Nic::E1000) => e1000::recv(buf),
+        Some(Nic::VirtIO) => virtio_net::recv(buf),
+        None => 0,
+    }
+}
+
+pub fn send(buf: &[u8]) {
+    match nic() {
+        Some(Nic::E1000) => e1000::send(buf),
+        Some(Nic::VirtIO) => virtio_net::send(buf),
+        None => {}
+    }
+}
+
+pub fn can_send() -> bool {
+    match nic() {
+        Some(Nic::E1000) => e1000::can_send(),
+        Some(Nic::VirtIO) => virtio_net::can_send(),
+        None => false,
+    }
+}
+
+pub fn can_recv() -> bool {
+    match nic() {
+        Some(Nic::E1000) => e1000::can_recv(),
+        Some(Nic::VirtIO) => virtio_net::can_recv(),
+        None => false,
+    }
+}
@@ -1,27 +1,415 @@
+#![allow(unused)]
+
+//! PCIe ECAM 枚举: 遍历总线和功能, 从设备树给出的 MMIO 窗口分配 BAR, 按厂商/设备 ID 匹配驱动
+
+extern crate alloc;
+
+use crate::board::board;
+use alloc::vec::Vec;
+use core::fmt;
+use spin::{Mutex, Once};
 use stdio::log;
-use volatile::Volatile;
 
-pub fn pci_init() {
-    for dev in 0..32 {
-        let off = dev << 11;
-        let base_addr = 0x30000000 + off;
-        let base = unsafe { core::slice::from_raw_parts_mut(base_addr as *mut Volatile<u32>, 10) };
-
-        let id = base[0].read();
-        // e1000
-        if id == 0x100e8086 {
-            log::info!("{id:x} found e1000");
-            base[1].write(7);
-            for i in 0..6 {
-                let old = base[4 + i].read();
-                base[4 + i].write(0xffffffff);
-                base[4 + i].write(old);
-            }
-            // e1000 register address
-            base[4].write(0x40000000);
-
-            crate::e1000::init(0x40000000, 0x10000 * 4);
+// 配置空间寄存器
+const VENDOR_ID: usize = 0x00;
+const DEVICE_ID: usize = 0x02;
+const COMMAND: usize = 0x04;
+const STATUS: usize = 0x06;
+const CLASS_REV: usize = 0x08;
+const HEADER_TYPE: usize = 0x0e;
+const BAR0: usize = 0x10;
+const CAP_PTR: usize = 0x34;
+const INTERRUPT_PIN: usize = 0x3d;
+// 桥
+const PRIMARY_BUS: usize = 0x18;
+const SECONDARY_BUS: usize = 0x19;
+const SUBORDINATE_BUS: usize = 0x1a;
+const MEMORY_BASE: usize = 0x20;
+const MEMORY_LIMIT: usize = 0x22;
+
+const COMMAND_IO: u16 = 1 << 0;
+const COMMAND_MEMORY: u16 = 1 << 1;
+const COMMAND_BUS_MASTER: u16 = 1 << 2;
+const STATUS_CAP_LIST: u16 = 1 << 4;
+
+const CAP_MSI: u8 = 0x05;
+const CAP_MSIX: u8 = 0x11;
+
+/// 桥的 MMIO 窗口粒度
+const BRIDGE_WINDOW_ALIGN: usize = 1 << 20;
+
+/// 总线/设备/功能号
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PciAddress {
+    pub bus: u8,
+    pub dev: u8,
+    pub func: u8,
+}
+
+impl fmt::Display for PciAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02x}:{:02x}.{}", self.bus, self.dev, self.func)
+    }
+}
+
+impl PciAddress {
+    #[inline]
+    fn config(&self, offset: usize) -> usize {
+        board().pci_ecam.start
+            + ((self.bus as usize) << 20)
+            + ((self.dev as usize) << 15)
+            + ((self.func as usize) << 12)
+            + offset
+    }
+
+    pub fn read32(&self, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile(self.config(offset) as *const u32) }
+    }
+
+    pub fn write32(&self, offset: usize, val: u32) {
+        unsafe { core::ptr::write_volatile(self.config(offset) as *mut u32, val) }
+    }
+
+    pub fn read16(&self, offset: usize) -> u16 {
+        unsafe { core::ptr::read_volatile(self.config(offset) as *const u16) }
+    }
+
+    pub fn write16(&self, offset: usize, val: u16) {
+        unsafe { core::ptr::write_volatile(self.config(offset) as *mut u16, val) }
+    }
+
+    pub fn read8(&self, offset: usize) -> u8 {
+        unsafe { core::ptr::read_volatile(self.config(offset) as *const u8) }
+    }
+
+    pub fn write8(&self, offset: usize, val: u8) {
+        unsafe { core::ptr::write_volatile(self.config(offset) as *mut u8, val) }
+    }
+}
+
+/// 已分配的 BAR
+#[derive(Clone, Copy, Debug)]
+pub struct Bar {
+    /// I/O BAR 不分配地址, 为 0
+    pub addr: usize,
+    pub size: usize,
+    pub io: bool,
+    pub prefetchable: bool,
+}
+
+/// MSI-X 能力
+#[derive(Clone, Copy, Debug)]
+pub struct Msix {
+    /// 能力结构在配置空间中的偏移
+    pub offset: usize,
+    /// 中断向量数
+    pub table_size: usize,
+    pub table_bar: usize,
+    pub table_offset: usize,
+    pub pba_bar: usize,
+    pub pba_offset: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct PciDevice {
+    pub addr: PciAddress,
+    pub vendor: u16,
+    pub device: u16,
+    /// class << 16 | subclass << 8 | prog-if
+    pub class: u32,
+    pub bars: [Option<Bar>; 6],
+    /// INTx 路由到的 PLIC 中断号
+    pub irq: Option<u32>,
+    /// MSI 能力偏移
+    pub msi: Option<usize>,
+    pub msix: Option<Msix>,
+}
+
+impl PciDevice {
+    /// 打开内存空间访问和总线主控 (DMA)
+    pub fn enable(&self) {
+        let command = self.addr.read16(COMMAND);
+        self.addr
+            .write16(COMMAND, command | COMMAND_MEMORY | COMMAND_BUS_MASTER);
+    }
+}
+
+/// PCI 驱动, probe 返回 false 表示不接管该设备
+pub struct PciDriver {
+    pub name: &'static str,
+    /// (vendor, device)
+    pub ids: &'static [(u16, u16)],
+    pub probe: fn(&PciDevice) -> bool,
+}
+
+static DRIVERS: Mutex<Vec<&'static PciDriver>> = Mutex::new(Vec::new());
+static DEVICES: Once<Vec<PciDevice>> = Once::new();
+
+/// 注册驱动, 需要在 pci_init 之前调用
+pub fn register_driver(driver: &'static PciDriver) {
+    DRIVERS.lock().push(driver);
+}
+
+/// 枚举到的设备
+pub fn devices() -> &'static [PciDevice] {
+    DEVICES.get().map(Vec::as_slice).unwrap_or(&[])
+}
+
+/// 枚举状态
+struct Scanner {
+    /// MMIO 窗口中下一个可用地址
+    mmio: usize,
+    mmio_end: usize,
+    next_bus: u8,
+    max_bus: u8,
+    devices: Vec<PciDevice>,
+}
+
+/// 设备在根总线上的上游: (根总线上桥的设备号, 中间各级设备号之和), 用于 INTx 轮换
+type Upstream = Option<(u8, usize)>;
+
+impl Scanner {
+    fn alloc_mmio(&mut self, size: usize, align: usize) -> Option<usize> {
+        let addr = self.mmio.checked_next_multiple_of(align)?;
+        let end = addr.checked_add(size).filter(|&end| end <= self.mmio_end)?;
+        self.mmio = end;
+        Some(addr)
+    }
+
+    fn scan_bus(&mut self, bus: u8, upstream: Upstream) {
+        for dev in 0..32 {
+            let addr = PciAddress { bus, dev, func: 0 };
+            if addr.read16(VENDOR_ID) == 0xffff {
+                continue;
+            }
+            let multi = addr.read8(HEADER_TYPE) & 0x80 != 0;
+            for func in 0..if multi { 8 } else { 1 } {
+                let addr = PciAddress { bus, dev, func };
+                if addr.read16(VENDOR_ID) != 0xffff {
+                    self.scan_function(addr, upstream);
+                }
+            }
+        }
+    }
+
+    fn scan_function(&mut self, addr: PciAddress, upstream: Upstream) {
+        match addr.read8(HEADER_TYPE) & 0x7f {
+            0 => {
+                let device = self.setup_endpoint(addr, upstream);
+                log::info!(
+                    "pci {} {:04x}:{:04x} class {:06x} irq {:?}",
+                    addr,
+                    device.vendor,
+                    device.device,
+                    device.class,
+                    device.irq
+                );
+                self.devices.push(device);
+            }
+            1 => self.setup_bridge(addr, upstream),
+            other => log::warn!("pci {addr}: unsupported header type {other}"),
+        }
+    }
+
+    fn setup_endpoint(&mut self, addr: PciAddress, upstream: Upstream) -> PciDevice {
+        let pin = addr.read8(INTERRUPT_PIN) as usize;
+        let irq = match (pin, upstream) {
+            (0, _) => None,
+            (pin, None) => board().pci_irq(addr.dev as usize, pin as u32),
+            (pin, Some((root_dev, offset))) => {
+                let pin = (pin - 1 + offset + addr.dev as usize) % 4 + 1;
+                board().pci_irq(root_dev as usize, pin as u32)
+            }
+        };
+        let (msi, msix) = capabilities(addr);
+        PciDevice {
+            addr,
+            vendor: addr.read16(VENDOR_ID),
+            device: addr.read16(DEVICE_ID),
+            class: addr.read32(CLASS_REV) >> 8,
+            bars: self.setup_bars(addr, 6),
+            irq,
+            msi,
+            msix,
+        }
+    }
+
+    /// 测量并分配 BAR, 期间关闭地址译码
+    ///
+    /// 有内存 BAR 分配失败时不打开内存译码, 避免设备响应未分配的地址
+    fn setup_bars(&mut self, addr: PciAddress, count: usize) -> [Option<Bar>; 6] {
+        let command = addr.read16(COMMAND);
+        addr.write16(COMMAND, command & !(COMMAND_IO | COMMAND_MEMORY));
+
+        let mut bars = [None; 6];
+        let mut placed = true;
+        let mut i = 0;
+        while i < count {
+            let offset = BAR0 + i * 4;
+            let old = addr.read32(offset);
+            addr.write32(offset, u32::MAX);
+            let mask = addr.read32(offset);
+            addr.write32(offset, old);
+            let index = i;
+            i += 1;
+            if mask == 0 {
+                continue;
+            }
+
+            if old & 1 == 1 {
+                let size = (!(mask & !0x3)).wrapping_add(1) as usize & 0xffff;
+                if size == 0 {
+                    continue;
+                }
+                bars[index] = Some(Bar {
+                    addr: 0,
+                    size,
+                    io: true,
+                    prefetchable: false,
+                });
+                continue;
+            }
+
+            let is64 = (old >> 1) & 0x3 == 0x2;
+            let mut mask = (mask & !0xf) as u64;
+            if is64 {
+                let high = offset + 4;
+                let old_high = addr.read32(high);
+                addr.write32(high, u32::MAX);
+                mask |= (addr.read32(high) as u64) << 32;
+                addr.write32(high, old_high);
+                i += 1;
+            } else {
+                mask |= 0xffff_ffff_0000_0000;
+            }
+            let size = (!mask).wrapping_add(1) as usize;
+            if size == 0 {
+                log::warn!("pci {addr}: BAR{index} reports size 0");
+                continue;
+            }
+            match self.alloc_mmio(size, size) {
+                Some(base) => {
+                    addr.write32(offset, base as u32 | (old & 0xf));
+                    if is64 {
+                        addr.write32(offset + 4, (base >> 32) as u32);
+                    }
+                    bars[index] = Some(Bar {
+                        addr: base,
+                        size,
+                        io: false,
+                        prefetchable: old & 0x8 != 0,
+                    });
+                }
+                None => {
+                    log::warn!("pci {addr}: no space for BAR{index} ({size:#x} bytes)");
+                    placed = false;
+                }
+            }
+        }
+
+        if placed {
+            addr.write16(COMMAND, command | COMMAND_MEMORY);
+        } else {
+            addr.write16(COMMAND, command & !COMMAND_MEMORY);
+        }
+        bars
+    }
+
+    /// 为桥分配下游总线号和 MMIO 窗口, 然后扫描下游总线
+    fn setup_bridge(&mut self, addr: PciAddress, upstream: Upstream) {
+        if self.next_bus > self.max_bus {
+            log::warn!("pci {addr}: out of bus numbers");
+            return;
+        }
+        let secondary = self.next_bus;
+        self.next_bus += 1;
+        addr.write8(PRIMARY_BUS, addr.bus);
+        addr.write8(SECONDARY_BUS, secondary);
+        addr.write8(SUBORDINATE_BUS, self.max_bus);
+
+        // 桥自身的 BAR
+        self.setup_bars(addr, 2);
+
+        self.mmio = self.mmio.next_multiple_of(BRIDGE_WINDOW_ALIGN);
+        let window = self.mmio;
+        let upstream = match upstream {
+            None => Some((addr.dev, 0)),
+            Some((root_dev, offset)) => Some((root_dev, offset + addr.dev as usize)),
+        };
+        self.scan_bus(secondary, upstream);
+        addr.write8(SUBORDINATE_BUS, self.next_bus - 1);
+
+        self.mmio = self.mmio.next_multiple_of(BRIDGE_WINDOW_ALIGN);
+        if self.mmio > window {
+            addr.write16(MEMORY_BASE, (window >> 16) as u16 & 0xfff0);
+            addr.write16(MEMORY_LIMIT, ((self.mmio - 1) >> 16) as u16 & 0xfff0);
+        } else {
+            // base > limit 表示关闭窗口
+            addr.write16(MEMORY_BASE, 0xfff0);
+            addr.write16(MEMORY_LIMIT, 0);
+        }
+        let command = addr.read16(COMMAND);
+        addr.write16(COMMAND, command | COMMAND_MEMORY | COMMAND_BUS_MASTER);
+        log::info!("pci {addr} bridge -> bus {secondary:02x}");
+    }
+}
+
+/// 遍历能力链表, 返回 (MSI, MSI-X)
+fn capabilities(addr: PciAddress) -> (Option<usize>, Option<Msix>) {
+    let (mut msi, mut msix) = (None, None);
+    if addr.read16(STATUS) & STATUS_CAP_LIST == 0 {
+        return (msi, msix);
+    }
+    let mut offset = (addr.read8(CAP_PTR) & 0xfc) as usize;
+    // 防止链表成环
+    for _ in 0..48 {
+        if offset == 0 {
             break;
         }
+        match addr.read8(offset) {
+            CAP_MSI => msi = Some(offset),
+            CAP_MSIX => {
+                let control = addr.read16(offset + 2);
+                let table = addr.read32(offset + 4);
+                let pba = addr.read32(offset + 8);
+                msix = Some(Msix {
+                    offset,
+                    table_size: (control & 0x7ff) as usize + 1,
+                    table_bar: (table & 0x7) as usize,
+                    table_offset: (table & !0x7) as usize,
+                    pba_bar: (pba & 0x7) as usize,
+                    pba_offset: (pba & !0x7) as usize,
+                });
+            }
+            _ => {}
+        }
+        offset = (addr.read8(offset + 1) & 0xfc) as usize;
+    }
+    (msi, msix)
+}
+
+/// 枚举所有设备并交给匹配的驱动
+pub fn pci_init() {
+    let info = board();
+    let mut scanner = Scanner {
+        mmio: info.pci_mmio.start,
+        mmio_end: info.pci_mmio.end,
+        next_bus: 1,
+        max_bus: ((info.pci_ecam.len() >> 20).clamp(1, 256) - 1) as u8,
+        devices: Vec::new(),
+    };
+    scanner.scan_bus(0, None);
+    let devices = DEVICES.call_once(|| scanner.devices);
+
+    let drivers = DRIVERS.lock();
+    for device in devices {
+        let driver = drivers
+            .iter()
+            .find(|driver| driver.ids.contains(&(device.vendor, device.device)));
+        if let Some(driver) = driver {
+            if (driver.probe)(device) {
+                log::info!("pci {}: bound to {}", device.addr, driver.name);
+            }
+        }
     }
 }
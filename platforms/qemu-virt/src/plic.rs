@@ -1,67 +1,60 @@
 #![allow(unused)]
 #![allow(non_snake_case)]
 
-use crate::trap::cpuid;
+use crate::{board::board, trap::cpuid};
 use core::ptr;
 
-/// qemu puts UART registers here in physical memory.
-pub const UART0: usize = 0x10000000;
-pub const UART0_IRQ: u32 = 10;
-
-/// virtio mmio interface
-pub const VIRTIO0: usize = 0x10001000;
-pub const VIRTIO0_IRQ: u32 = 1;
-
 /// core local interruptor (CLINT), which contains the timer.
 pub const CLINT: usize = 0x2000000;
 pub const CLINT_MTIME: usize = CLINT + 0xBFF8;
 pub const CLINT_MTIMECMP: usize = CLINT + 0x4000;
 
-// qemu puts platform-level interrupt controller (PLIC) here.
-pub const PLIC_BASE: usize = 0x0c000000;
-
-// we'll place the e1000 registers at this address.
-pub const E1000_REGS: usize = 0x40000000;
-pub const E1000_IRQ: usize = 33;
-
-// qemu -machine virt puts PCIe config space here.
-pub const ECAM: usize = 0x30000000;
+// PLIC 地址来自设备树
+#[inline]
+fn PLIC_BASE() -> usize {
+    board().plic.start
+}
 
-const PLIC_PRIORITY: usize = PLIC_BASE;
-const PLIC_PENDING: usize = PLIC_BASE + 0x1000;
+fn PLIC_PRIORITY(irq: u32) -> usize {
+    PLIC_BASE() + irq as usize * 4
+}
 
 fn PLIC_MENABLE(hart_id: usize) -> usize {
-    PLIC_BASE + 0x2000 + hart_id * 0x100
+    PLIC_BASE() + 0x2000 + hart_id * 0x100
 }
 
 fn PLIC_SENABLE(hart_id: usize) -> usize {
-    PLIC_BASE + 0x2080 + hart_id * 0x100
+    PLIC_BASE() + 0x2080 + hart_id * 0x100
 }
 
 fn PLIC_MPRIORITY(hart_id: usize) -> usize {
-    PLIC_BASE + 0x200000 + hart_id * 0x2000
+    PLIC_BASE() + 0x200000 + hart_id * 0x2000
 }
 
 fn PLIC_SPRIORITY(hart_id: usize) -> usize {
-    PLIC_BASE + 0x201000 + hart_id * 0x2000
+    PLIC_BASE() + 0x201000 + hart_id * 0x2000
 }
 
 fn PLIC_MCLAIM(hart_id: usize) -> usize {
-    PLIC_BASE + 0x200004 + hart_id * 0x2000
+    PLIC_BASE() + 0x200004 + hart_id * 0x2000
 }
 
 fn PLIC_SCLAIM(hart_id: usize) -> usize {
-    PLIC_BASE + 0x201004 + hart_id * 0x2000
+    PLIC_BASE() + 0x201004 + hart_id * 0x2000
+}
+
+/// 需要使能的中断: UART, virtio, PCIe INTx
+fn irqs() -> impl Iterator<Item = u32> {
+    let info = board();
+    core::iter::once(info.uart_irq)
+        .chain(info.virtio_mmio.iter().map(|&(_, irq)| irq))
+        .chain(info.pci_irq_map.iter().map(|&(_, _, irq)| irq))
 }
 
 pub fn plic_init() {
     // set desired IRQ priorities non-zero (otherwise disable)
-    write(PLIC_BASE + (UART0_IRQ * 4) as usize, 1);
-    write(PLIC_BASE + (VIRTIO0_IRQ * 4) as usize, 1);
-
-    // set PCIE IRQS (32 to 35), for e1000
-    for irq in 1..35 {
-        write(PLIC_BASE + irq * 4, 1);
+    for irq in irqs() {
+        write(PLIC_PRIORITY(irq), 1);
     }
 }
 
@@ -69,14 +62,11 @@ pub fn plic_init_hart() {
     // 目前只有一个 CPU
     let hart_id = cpuid();
 
-    // Set UART's enable bit for this hart's S-mode.
-    write(
-        PLIC_SENABLE(hart_id),
-        (1 << UART0_IRQ) | (1 << VIRTIO0_IRQ),
-    );
-
-    // e1000
-    write(PLIC_SENABLE(hart_id) + 4, 0xffffffff);
+    // Set enable bits for this hart's S-mode.
+    for irq in irqs() {
+        let addr = PLIC_SENABLE(hart_id) + (irq / 32) as usize * 4;
+        write(addr, read(addr) | 1 << (irq % 32));
+    }
 
     // Set this hart's S-mode pirority threshold to 0.
     write(PLIC_SPRIORITY(hart_id), 0);
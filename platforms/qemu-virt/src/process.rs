@@ -0,0 +1,303 @@
+#![allow(unused)]
+
+extern crate alloc;
+
+use crate::{
+    consts::*,
+    elf::{Elf, PF_R, PF_W, PF_X},
+    frame::FrameTracker,
+    syscall::{handle_user_syscall, sys_spawn},
+    trap::{pop_on, push_off},
+    vm::{flags, AddressSpace, KERNEL_SPACE, PAGE_SIZE},
+};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use kernel_context::{
+    foreign::{ForeignContext, MultislotPortal},
+    LocalContext,
+};
+use riscv::register::{scause, sepc, stval};
+use spin::{Mutex, Once};
+use stdio::log;
+
+/// 传送门在内核和用户地址空间中的虚拟地址: 最高的一页
+pub const PORTAL_TRANSIT: usize = usize::MAX - PAGE_SIZE + 1;
+
+/// 单核, 传送门一次往返期间不会切换线程, 一个插槽即可
+static PORTAL: Once<Mutex<&'static mut MultislotPortal>> = Once::new();
+
+/// 在内核地址空间中映射传送门, 需要在开启分页后调用
+pub fn init_portal() {
+    assert!(MultislotPortal::calculate_size(1) <= PAGE_SIZE);
+    // 传送门页在整个内核生命周期内存在
+    let frame = core::mem::ManuallyDrop::new(FrameTracker::new().expect("out of frames"));
+    KERNEL_SPACE
+        .wait()
+        .lock()
+        .map(PORTAL_TRANSIT, frame.0, PAGE_SIZE, flags::RWX);
+    let portal = unsafe { MultislotPortal::init_transit(PORTAL_TRANSIT, 1) };
+    PORTAL.call_once(|| Mutex::new(portal));
+}
+
+/// 运行在独立地址空间中的用户态程序
+pub struct Process {
+    pub pid: usize,
+    space: AddressSpace,
+    context: ForeignContext,
+    frames: Vec<FrameTracker>,
+}
+
+impl Process {
+    /// 创建只映射了传送门的空进程
+    pub fn new() -> Self {
+        static NEXT_PID: AtomicUsize = AtomicUsize::new(1);
+
+        let mut space = AddressSpace::new();
+        let portal = KERNEL_SPACE
+            .wait()
+            .lock()
+            .translate(PORTAL_TRANSIT)
+            .expect("portal uninit");
+        space.map(PORTAL_TRANSIT, portal, PAGE_SIZE, flags::RWX);
+
+        let satp = space.satp();
+        Self {
+            pid: NEXT_PID.fetch_add(1, Ordering::Relaxed),
+            space,
+            context: ForeignContext {
+                context: LocalContext::user(0),
+                satp,
+            },
+            frames: Vec::new(),
+        }
+    }
+
+    /// 为 [vaddr, vaddr + size) 分配清零的页帧并以用户权限映射
+    ///
+    /// 已映射的用户页合并权限, 与内核映射重叠时返回错误
+    pub fn map_anonymous(
+        &mut self,
+        vaddr: usize,
+        size: usize,
+        flags: usize,
+    ) -> Result<(), &'static str> {
+        let start = vaddr & !(PAGE_SIZE - 1);
+        let end = vaddr
+            .checked_add(size)
+            .and_then(|end| end.checked_next_multiple_of(PAGE_SIZE))
+            .ok_or("segment out of range")?;
+        for va in (start..end).step_by(PAGE_SIZE) {
+            match self.space.pte(va) {
+                // 相邻段可能共享一页
+                Some(pte) if pte & flags::U != 0 => {
+                    self.space.add_flags(va, flags);
+                }
+                Some(_) => return Err("segment overlaps kernel mapping"),
+                None if self.space.translate(va).is_some() => {
+                    return Err("segment overlaps kernel mapping")
+                }
+                None => {
+                    let frame = FrameTracker::new().ok_or("out of frames")?;
+                    self.space.map(va, frame.0, PAGE_SIZE, flags | flags::U);
+                    self.frames.push(frame);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 将 data 写入用户地址 vaddr, 地址不是用户页时返回 false
+    pub fn write(&mut self, vaddr: usize, data: &[u8]) -> bool {
+        self.copy(vaddr, data.len(), |paddr, offset, len| unsafe {
+            core::ptr::copy_nonoverlapping(data[offset..].as_ptr(), paddr as *mut u8, len);
+        })
+    }
+
+    /// 从用户地址 vaddr 读取到 buf, 地址不是用户页时返回 false
+    pub fn read(&self, vaddr: usize, buf: &mut [u8]) -> bool {
+        self.copy(vaddr, buf.len(), |paddr, offset, len| unsafe {
+            core::ptr::copy_nonoverlapping(paddr as *const u8, buf[offset..].as_mut_ptr(), len);
+        })
+    }
+
+    /// 逐页转换用户地址, 内核恒等映射因此物理地址可以直接访问
+    ///
+    /// 只接受带 U 位的页, 传送门等内核映射不能被用户读写
+    fn copy<F>(&self, vaddr: usize, len: usize, mut f: F) -> bool
+    where
+        F: FnMut(usize, usize, usize),
+    {
+        let mut offset = 0;
+        while offset < len {
+            let Some(va) = vaddr.checked_add(offset) else {
+                return false;
+            };
+            let user = self.space.pte(va).is_some_and(|pte| pte & flags::U != 0);
+            let paddr = match self.space.translate(va) {
+                Some(paddr) if user => paddr,
+                _ => return false,
+            };
+            let n = (PAGE_SIZE - va % PAGE_SIZE).min(len - offset);
+            f(paddr, offset, n);
+            offset += n;
+        }
+        true
+    }
+
+    /// 加载 ELF 可执行文件, 映射 PT_LOAD 段并按 System V ABI 布置用户栈
+    pub fn from_elf(data: &[u8], argv: &[&str], envp: &[&str]) -> Result<Self, &'static str> {
+        let elf = Elf::parse(data)?;
+        let mut process = Self::new();
+        for ph in elf.loads() {
+            if ph.p_memsz == 0 {
+                continue;
+            }
+            let end = ph.p_vaddr.checked_add(ph.p_memsz);
+            if ph.p_filesz > ph.p_memsz
+                || end.map_or(true, |end| end > USER_STACK_TOP - USER_STACK_SIZE)
+            {
+                return Err("bad PT_LOAD segment");
+            }
+            let mut flags = 0;
+            if ph.p_flags & PF_R != 0 {
+                flags |= flags::RO;
+            }
+            if ph.p_flags & PF_W != 0 {
+                flags |= flags::RW;
+            }
+            if ph.p_flags & PF_X != 0 {
+                flags |= flags::RX;
+            }
+            process.map_anonymous(ph.p_vaddr, ph.p_memsz, flags)?;
+            // 页帧已清零, .bss 部分无需处理
+            let segment = elf.segment_data(&ph).ok_or("truncated segment")?;
+            if !process.write(ph.p_vaddr, segment) {
+                return Err("segment not mapped");
+            }
+        }
+
+        let sp = process.init_stack(argv, envp)?;
+        process.set_entry(elf.entry, sp);
+        Ok(process)
+    }
+
+    /// 布置用户栈: 栈顶依次存放字符串, 低处为 argc, argv[], NULL, envp[], NULL, AT_NULL
+    ///
+    /// 同时将 argc, argv, envp 放入 a0-a2, 方便不解析栈的程序
+    fn init_stack(&mut self, argv: &[&str], envp: &[&str]) -> Result<usize, &'static str> {
+        let bottom = USER_STACK_TOP - USER_STACK_SIZE;
+        self.map_anonymous(bottom, USER_STACK_SIZE, flags::RW)?;
+
+        let mut sp = USER_STACK_TOP;
+        let mut push_str = |process: &mut Self, s: &str| -> Result<usize, &'static str> {
+            sp = sp
+                .checked_sub(s.len() + 1)
+                .filter(|&sp| sp >= bottom)
+                .ok_or("args too long")?;
+            process.write(sp, s.as_bytes());
+            process.write(sp + s.len(), &[0]);
+            Ok(sp)
+        };
+        let argv_ptrs = argv
+            .iter()
+            .map(|s| push_str(self, s))
+            .collect::<Result<Vec<_>, _>>()?;
+        let envp_ptrs = envp
+            .iter()
+            .map(|s| push_str(self, s))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut words = Vec::with_capacity(argv.len() + envp.len() + 5);
+        words.push(argv.len());
+        words.extend_from_slice(&argv_ptrs);
+        words.push(0);
+        words.extend_from_slice(&envp_ptrs);
+        words.push(0);
+        // auxv: 只有 AT_NULL
+        words.extend_from_slice(&[0, 0]);
+
+        let size = words.len() * core::mem::size_of::<usize>();
+        let sp = sp
+            .checked_sub(size)
+            .map(|sp| sp & !0xf)
+            .filter(|&sp| sp >= bottom)
+            .ok_or("args too long")?;
+        for (i, word) in words.iter().enumerate() {
+            self.write(sp + i * 8, &word.to_ne_bytes());
+        }
+
+        let context = self.context();
+        *context.a_mut(0) = argv.len();
+        *context.a_mut(1) = sp + 8;
+        *context.a_mut(2) = sp + 8 * (argv.len() + 2);
+        Ok(sp)
+    }
+
+    /// 设置入口和用户栈
+    pub fn set_entry(&mut self, pc: usize, sp: usize) {
+        *self.context.context.pc_mut() = pc;
+        *self.context.context.sp_mut() = sp;
+    }
+
+    /// 用户上下文
+    pub fn context(&mut self) -> &mut LocalContext {
+        &mut self.context.context
+    }
+
+    /// 在当前线程中执行进程直到其退出, 返回退出码
+    pub fn run(&mut self) -> i32 {
+        use scause::{Exception, Trap};
+        loop {
+            // 传送门往返期间不能被调度器打断，用户态中断会先回到这里
+            let sstatus = push_off();
+            unsafe {
+                let mut portal = PORTAL.wait().lock();
+                self.context.execute(&mut **portal, ());
+            }
+            // 恢复中断前读出陷入原因, 之后的中断会覆盖这些寄存器
+            let (cause, sepc, stval) = (scause::read().cause(), sepc::read(), stval::read());
+            pop_on(sstatus);
+
+            match cause {
+                Trap::Exception(Exception::UserEnvCall) => {
+                    self.context.context.move_next();
+                    if let Some(code) = handle_user_syscall(self) {
+                        return code;
+                    }
+                }
+                Trap::Interrupt(_) => {
+                    // 中断仍处于 pending 状态, 恢复中断状态后由调度器处理
+                }
+                cause => {
+                    log::error!(
+                        "process {}: {:?}, sepc {:x}, stval {:x}",
+                        self.pid,
+                        cause,
+                        sepc,
+                        stval
+                    );
+                    return -1;
+                }
+            }
+        }
+    }
+}
+
+/// 创建一个线程执行进程, 返回线程 tid
+pub fn spawn(mut process: Process) -> usize {
+    sys_spawn(
+        async move {
+            let pid = process.pid;
+            let code = process.run();
+            log::info!("process {pid} exited with {code}");
+        },
+        false,
+        DEFAULT_PRIORITY,
+        STACK_SIZE,
+    )
+}
+
+/// 加载 ELF 并在新线程中执行, 返回线程 tid
+pub fn exec(data: &[u8], argv: &[&str], envp: &[&str]) -> Result<usize, &'static str> {
+    Process::from_elf(data, argv, envp).map(spawn)
+}
@@ -1,13 +1,21 @@
 #![allow(dead_code)]
 use crate::{
-    tasks::{handle_append_task, spawn, Task, leak_boxed_PinBoxFuture},
+    async_executor::AsyncTask,
+    consts::*,
+    process::Process,
+    tasks::{
+        cancel, current_tid, exit, handle_append_task, kill, leak_boxed_AsyncTask, set_priority,
+        spawn, task_infos, Task,
+    },
     thread,
     timer::sleep,
+    trace::{self, SchedEvent},
     trap::{pop_on, push_off},
-    consts::*,
+    vm::PAGE_SIZE,
 };
-use alloc::boxed::Box;
 use core::future::Future;
+use platform::TaskInfo;
+use stdio::log;
 
 // 流程：调用 sys_xxx => 调用 syscall 函数并传入系统调用号和参数 => syscall 通过 e_call 函数陷入调度器 (调度器使用 handle_syscall 处理系统调用 => 调度器返回至 e_call 的下一个指令) => syscall 返回系统调用结果
 
@@ -17,18 +25,27 @@ pub fn handle_syscall(mut task: Task) -> Option<Task> {
     let cx = &mut lock.ctx;
     let syscall_id = cx.x(17);
     let arg0: usize = cx.x(10);
-    let _arg1: usize = cx.x(11);
+    let arg1: usize = cx.x(11);
     let _arg2: usize = cx.x(12);
 
     drop(cx);
     drop(lock);
 
+    trace::record(task.tid, SchedEvent::Syscall(syscall_id));
+
+    // 被抢占时收到的 kill, 线程进入系统调用时不持有锁, 可以结束
+    if task.kill_pending {
+        exit(task);
+        return None;
+    }
+
     // 部分系统调用需要直接用到 task, 但不一定将 task 返回
     // sleep 系统调用会将 task 插入到等待队列中
     use thread::TaskStatus::*;
     let (mut task, result) = match syscall_id {
         SYSCALL_SLEEP => {
             task.set_status(Blocking);
+            task.stats.voluntary += 1;
             sleep(task, arg0)
         }
         SYSCALL_GET_TID => {
@@ -41,9 +58,46 @@ pub fn handle_syscall(mut task: Task) -> Option<Task> {
         }
         SYSCALL_YIELD => {
             task.set_status(Blocking);
+            task.stats.voluntary += 1;
             (Some(task), 0)
         }
-        SYSCALL_EXIT => (None, 0),
+        SYSCALL_SET_PRIORITY => {
+            let ret = if arg0 == task.tid {
+                task.priority = arg1.min(MAX_PRIORITY);
+                0
+            } else if set_priority(arg0, arg1) {
+                0
+            } else {
+                usize::MAX
+            };
+            (Some(task), ret)
+        }
+        SYSCALL_KILL => {
+            if arg0 == task.tid && arg0 != IO_TASK_TID {
+                exit(task);
+                (None, 0)
+            } else if kill(arg0) {
+                (Some(task), 0)
+            } else {
+                (Some(task), usize::MAX)
+            }
+        }
+        SYSCALL_CANCEL => {
+            if cancel(&task, arg0) {
+                (Some(task), 0)
+            } else {
+                (Some(task), usize::MAX)
+            }
+        }
+        SYSCALL_TASKS => {
+            let buf = unsafe { core::slice::from_raw_parts_mut(arg0 as *mut TaskInfo, arg1) };
+            let count = task_infos(&task, buf);
+            (Some(task), count)
+        }
+        SYSCALL_EXIT => {
+            trace::record(task.tid, SchedEvent::Exit);
+            (None, 0)
+        }
         _ => panic!("Unsupported syscall_id: {}", syscall_id),
     };
 
@@ -56,6 +110,64 @@ pub fn handle_syscall(mut task: Task) -> Option<Task> {
     task
 }
 
+/// 处理用户态进程的 ecall, 进程退出时返回退出码
+///
+/// 在承载进程的内核线程中执行，可以继续发起内核系统调用
+pub fn handle_user_syscall(process: &mut Process) -> Option<i32> {
+    let cx = process.context();
+    let syscall_id = cx.x(17);
+    let args = [cx.x(10), cx.x(11), cx.x(12)];
+
+    let result = match syscall_id {
+        USER_SYSCALL_WRITE => {
+            // 只支持 stdout 与 stderr
+            let (fd, buf, len) = (args[0], args[1], args[2]);
+            if fd != 1 && fd != 2 {
+                usize::MAX
+            } else {
+                // 长度由用户决定, 按页经栈上缓冲区复制, 返回写出的字节数
+                let mut chunk = [0u8; PAGE_SIZE];
+                let mut written = 0;
+                while written < len {
+                    let n = (len - written).min(PAGE_SIZE);
+                    if !process.read(buf + written, &mut chunk[..n]) {
+                        break;
+                    }
+                    for s in chunk[..n].utf8_chunks() {
+                        stdio::print!("{}", s.valid());
+                        if !s.invalid().is_empty() {
+                            stdio::print!("{}", char::REPLACEMENT_CHARACTER);
+                        }
+                    }
+                    written += n;
+                }
+                if written == 0 && len > 0 {
+                    usize::MAX
+                } else {
+                    written
+                }
+            }
+        }
+        USER_SYSCALL_EXIT => return Some(args[0] as i32),
+        // 用户进程只能修改承载它的线程的优先级
+        SYSCALL_SET_PRIORITY if Some(args[0]) != current_tid() => usize::MAX,
+        SYSCALL_SLEEP | SYSCALL_GET_TID | SYSCALL_YIELD | SYSCALL_SET_PRIORITY => {
+            syscall(syscall_id, args)
+        }
+        _ => {
+            log::error!(
+                "process {}: unsupported syscall {}",
+                process.pid,
+                syscall_id
+            );
+            return Some(-1);
+        }
+    };
+
+    *process.context().x_mut(10) = result;
+    None
+}
+
 pub fn syscall(id: usize, args: [usize; 3]) -> usize {
     let mut ret: usize;
 
@@ -90,12 +202,12 @@ pub fn sys_get_tid() -> usize {
     tid
 }
 
-pub fn sys_spawn<F>(f: F, is_io: bool) -> usize
+pub fn sys_spawn<F>(f: F, is_io: bool, priority: usize, stack_size: usize) -> usize
 where
     F: Future<Output = ()> + Send + 'static,
 {
     let sstatus = push_off();
-    let ret = spawn(f, is_io);
+    let ret = spawn(f, is_io, priority, stack_size);
     pop_on(sstatus);
     ret
 }
@@ -104,14 +216,44 @@ pub fn sys_append_task<F>(future: F) -> usize
 where
     F: Future<Output = ()> + Send + 'static,
 {
-    let addr = leak_boxed_PinBoxFuture(Box::pin(future));
-    syscall(SYSCALL_APPEND_TASK, [addr, 0, 0])
+    sys_append_cancellable(future).0
+}
+
+/// 返回 (tid, cid), cid 用于 sys_cancel
+pub fn sys_append_cancellable<F>(future: F) -> (usize, usize)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let task = AsyncTask::new(future);
+    let cid = task.id();
+    let addr = leak_boxed_AsyncTask(task);
+    (syscall(SYSCALL_APPEND_TASK, [addr, 0, 0]), cid)
 }
 
 pub fn sys_yield() {
     syscall(SYSCALL_YIELD, [0, 0, 0]);
 }
 
+/// 修改线程优先级，成功返回 0，线程不存在返回 usize::MAX
+pub fn sys_set_priority(tid: usize, priority: usize) -> usize {
+    syscall(SYSCALL_SET_PRIORITY, [tid, priority, 0])
+}
+
+/// 结束线程 tid，成功返回 0，线程不存在返回 usize::MAX
+pub fn sys_kill(tid: usize) -> usize {
+    syscall(SYSCALL_KILL, [tid, 0, 0])
+}
+
+/// 取消协程 cid，协程在下一次被轮询前被丢弃，请求送达返回 0，否则返回 usize::MAX
+pub fn sys_cancel(cid: usize) -> usize {
+    syscall(SYSCALL_CANCEL, [cid, 0, 0])
+}
+
+/// 将线程信息写入 buf，返回线程总数
+pub fn sys_tasks(buf: &mut [TaskInfo]) -> usize {
+    syscall(SYSCALL_TASKS, [buf.as_mut_ptr() as usize, buf.len(), 0])
+}
+
 pub fn sys_exit() {
     syscall(SYSCALL_EXIT, [0, 0, 0]);
 }
@@ -3,11 +3,14 @@
 extern crate alloc;
 
 use crate::{
-    async_executor::{AsyncTask, Executor, PinBoxFuture},
+    async_executor::{AsyncTask, Executor, CURRENT_CID, NO_COROUTINE},
+    board,
     syscall::{sys_exit, sys_get_tid},
     thread,
     thread::{TCBlock, TaskStatus},
-    IO_TASK_TID,
+    timer::TIMERS,
+    trace::{self, SchedEvent, TaskStats},
+    IO_TASK_TID, MAX_PRIORITY, MICRO_PER_SEC,
 };
 use alloc::{boxed::Box, collections::VecDeque, sync::Arc, vec, vec::Vec};
 use core::{
@@ -15,8 +18,12 @@ use core::{
     pin::Pin,
     sync::atomic::{AtomicUsize, Ordering},
 };
+use platform::{TaskInfo, TaskState};
 use spin::{Lazy, Mutex};
-use stdio::log::{self, info};
+use stdio::{
+    log::{self, info},
+    print, println,
+};
 
 // MLFQ 层数
 const NUM_LEVELS: usize = 2;
@@ -54,6 +61,10 @@ impl MlfqStruct {
     }
 
     pub fn get_task_by_tid(&mut self, tid: usize) -> Option<Task> {
+        if self.task.as_ref().map_or(false, |t| t.tid == tid) {
+            return self.task.take();
+        }
+
         let queue = &mut self.queue;
         for i in 0..NUM_LEVELS {
             for j in 0..queue[i].len() {
@@ -67,11 +78,38 @@ impl MlfqStruct {
     }
 
     pub fn add_task_to_queue(&mut self, task: Task) {
-        if task.io {
-            self.queue[0].push_back(task);
+        let queue = if task.io {
+            &mut self.queue[0]
         } else {
-            self.queue[1].push_back(task);
+            &mut self.queue[1]
+        };
+        // 同一层内按优先级排序，相同优先级保持先进先出
+        let pos = queue
+            .iter()
+            .position(|t| t.priority > task.priority)
+            .unwrap_or(queue.len());
+        queue.insert(pos, task);
+    }
+
+    pub fn task_mut(&mut self, tid: usize) -> Option<&mut Task> {
+        self.task
+            .iter_mut()
+            .chain(self.queue.iter_mut().flatten())
+            .find(|t| t.tid == tid)
+    }
+
+    pub fn set_priority(&mut self, tid: usize, priority: usize) -> bool {
+        if let Some(task) = self.task.as_mut().filter(|t| t.tid == tid) {
+            task.priority = priority;
+            return true;
+        }
+        // 修改后需要重新排序
+        if let Some(mut task) = self.get_task_by_tid(tid) {
+            task.priority = priority;
+            self.add_task_to_queue(task);
+            return true;
         }
+        false
     }
 
     pub fn add_task_transient(&mut self, task: Task) {
@@ -88,6 +126,19 @@ impl MlfqStruct {
 }
 
 // MLFQ
+/// 正在执行的线程, 调度器和启动阶段为 NO_TASK
+static CURRENT: AtomicUsize = AtomicUsize::new(NO_TASK);
+const NO_TASK: usize = usize::MAX;
+
+/// 当前线程 tid, 不需要系统调用, 可在分配器等无法 ecall 的地方使用
+#[inline]
+pub fn current_tid() -> Option<usize> {
+    match CURRENT.load(Ordering::Relaxed) {
+        NO_TASK => None,
+        tid => Some(tid),
+    }
+}
+
 static MLFQ: Lazy<Mutex<MlfqStruct>> = Lazy::new(|| {
     let mut v = Vec::new();
     for _ in 0..NUM_LEVELS {
@@ -110,10 +161,20 @@ pub struct Task {
     pub executor: Arc<Mutex<Executor>>,
     /// is I/O task
     pub io: bool,
+    /// 优先级, 0 最高
+    pub priority: usize,
+    /// 调度统计
+    pub stats: TaskStats,
+    /// 被切换出去时正在轮询的协程
+    cid: AtomicUsize,
+    /// 上次因系统调用切换出去, 此时线程不会持有锁, 可以直接结束
+    pub in_syscall: bool,
+    /// 被 kill 时正被抢占, 在下一次系统调用时结束
+    pub kill_pending: bool,
 }
 
 impl Task {
-    pub fn new(tcb: TCBlock, executor: Arc<Mutex<Executor>>, is_io: bool) -> Task {
+    pub fn new(tcb: TCBlock, executor: Arc<Mutex<Executor>>, is_io: bool, priority: usize) -> Task {
         static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
         let tid = NEXT_ID.fetch_add(1, Ordering::Relaxed);
 
@@ -122,6 +183,11 @@ impl Task {
             tcb: tcb,
             executor,
             io: is_io,
+            priority: priority.min(MAX_PRIORITY),
+            stats: TaskStats::default(),
+            cid: AtomicUsize::new(NO_COROUTINE),
+            in_syscall: false,
+            kill_pending: false,
         }
     }
 }
@@ -165,26 +231,75 @@ impl Task {
                 sys_exit();
             });
 
-            return Some(Self::new(tcb, executor, true));
+            let task = Self::new(tcb, executor, true, self.priority);
+            self.stats.steals += 1;
+            trace::record(self.tid, SchedEvent::Steal(task.tid));
+            return Some(task);
         }
         None
     }
 
-    pub fn append(&self, future: PinBoxFuture) {
+    pub fn append(&self, task: AsyncTask) {
+        unsafe {
+            self.executor.force_unlock();
+        }
+        self.executor.lock().spawn(task);
+    }
+
+    /// 通知此线程取消协程 cid, 请求队列已满时返回 false
+    pub fn cancel(&self, cid: usize) -> bool {
         unsafe {
             self.executor.force_unlock();
         }
-        self.executor.lock().spawn(AsyncTask::new(future));
+        self.executor.lock().cancel(cid)
+    }
+
+    /// 丢弃线程中的所有协程
+    pub fn clear(&self) {
+        unsafe {
+            self.executor.force_unlock();
+        }
+        self.executor.lock().clear();
     }
 
     pub fn run(&self) {
+        CURRENT.store(self.tid, Ordering::Relaxed);
+        CURRENT_CID.store(self.cid.load(Ordering::Relaxed), Ordering::Relaxed);
         unsafe {
             self.tcb.lock().execute();
         }
+        let cid = CURRENT_CID.swap(NO_COROUTINE, Ordering::Relaxed);
+        self.cid.store(cid, Ordering::Relaxed);
+        CURRENT.store(NO_TASK, Ordering::Relaxed);
+    }
+
+    /// 检查线程栈，溢出时 panic
+    pub fn check_stack(&self) {
+        let tcb = self.tcb.lock();
+        if tcb.stack_overflowed() {
+            panic!(
+                "stack overflow in thread {}: sp {:#x}, stack {:#x}..{:#x}",
+                self.tid,
+                tcb.ctx.sp(),
+                tcb.stack,
+                tcb.stack + tcb.stack_size
+            );
+        }
+    }
+
+    pub fn info(&self, state: TaskState) -> TaskInfo {
+        TaskInfo {
+            tid: self.tid,
+            io: self.io,
+            priority: self.priority,
+            state,
+            coroutines: self.queue_len(),
+            cpu_time_us: self.stats.run_time / (board::timebase() / MICRO_PER_SEC),
+        }
     }
 }
 
-pub(crate) fn spawn<F>(f: F, is_io: bool) -> usize
+pub(crate) fn spawn<F>(f: F, is_io: bool, priority: usize, stack_size: usize) -> usize
 where
     F: Future<Output = ()> + Send + 'static,
 {
@@ -192,12 +307,15 @@ where
     executor.lock().spawn(AsyncTask::new(f));
     let thread_executor = executor.clone();
 
-    let tcb = thread::spawn(move || {
-        thread_executor.lock().run();
-        sys_exit();
-    });
+    let tcb = thread::spawn_with_stack(
+        move || {
+            thread_executor.lock().run();
+            sys_exit();
+        },
+        stack_size,
+    );
 
-    let task = Task::new(tcb, executor, is_io);
+    let task = Task::new(tcb, executor, is_io, priority);
     let tid = task.tid;
 
     add_task_to_queue(task);
@@ -224,30 +342,175 @@ pub fn get_task_by_tid(tid: usize) -> Option<Task> {
     MLFQ.lock().get_task_by_tid(tid)
 }
 
-pub fn handle_append_task(task: Task, future: usize) -> (Task, usize) {
+/// 修改线程优先级, 线程可能在 MLFQ 中，也可能在 TIMERS 中等待
+pub fn set_priority(tid: usize, priority: usize) -> bool {
+    let priority = priority.min(MAX_PRIORITY);
+    if MLFQ.lock().set_priority(tid, priority) {
+        return true;
+    }
+    if let Some(cond) = TIMERS.lock().iter_mut().find(|c| c.task.tid == tid) {
+        cond.task.priority = priority;
+        return true;
+    }
+    false
+}
+
+/// 结束线程 tid 并丢弃其所有协程, IO 线程不能被结束
+///
+/// 协程在调度器中被 drop。线程上次因系统调用或 sleep 切换出去时不持有锁, 立即结束;
+/// 被抢占的线程可能持有锁, 只做标记, 在它下一次系统调用时结束
+pub fn kill(tid: usize) -> bool {
+    if tid == IO_TASK_TID {
+        return false;
+    }
+
+    let mut mlfq = MLFQ.lock();
+    let task = match mlfq.task_mut(tid) {
+        Some(task) if !task.in_syscall => {
+            task.kill_pending = true;
+            return true;
+        }
+        Some(_) => mlfq.get_task_by_tid(tid),
+        None => {
+            drop(mlfq);
+            TIMERS
+                .lock()
+                .remove_by(|cond| cond.task.tid == tid)
+                .map(|cond| cond.task)
+        }
+    };
+
+    match task {
+        Some(task) => {
+            exit(task);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 丢弃线程的所有协程并记录退出, 线程随 task 一起释放
+pub fn exit(task: Task) {
+    task.clear();
+    trace::record(task.tid, SchedEvent::Exit);
+}
+
+/// 遍历 MLFQ、时间片未用完的任务以及 TIMERS 中的所有线程
+///
+/// 不包括正在执行系统调用的线程
+pub fn for_each_task<F>(mut f: F)
+where
+    F: FnMut(&Task, TaskState),
+{
+    let mlfq = MLFQ.lock();
+    mlfq.task.iter().for_each(|t| f(t, TaskState::Ready));
+    mlfq.queue
+        .iter()
+        .flatten()
+        .for_each(|t| f(t, TaskState::Ready));
+    drop(mlfq);
+
+    TIMERS
+        .lock()
+        .iter()
+        .for_each(|cond| f(&cond.task, TaskState::Sleeping));
+}
+
+/// 打印调度器状态, 给 panic 使用
+///
+/// panic 可能发生在持有 MLFQ 或 TIMERS 的时候, 所以只尝试加锁, 也不分配内存
+pub fn dump() {
+    match current_tid() {
+        Some(tid) => println!("current thread: {tid}"),
+        None => println!("current thread: none (scheduler)"),
+    }
+    match MLFQ.try_lock() {
+        Some(mlfq) => {
+            println!("mlfq level: {}", mlfq.level);
+            if let Some(task) = &mlfq.task {
+                println!("  unfinished slice: tid {}", task.tid);
+            }
+            for (level, queue) in mlfq.queue.iter().enumerate() {
+                if queue.is_empty() {
+                    continue;
+                }
+                print!("  level {level}:");
+                queue.iter().for_each(|task| print!(" {}", task.tid));
+                println!();
+            }
+            mlfq.task
+                .iter()
+                .chain(mlfq.queue.iter().flatten())
+                .for_each(dump_stats);
+        }
+        None => println!("mlfq: locked"),
+    }
+    match TIMERS.try_lock() {
+        Some(timers) => {
+            print!("sleeping:");
+            timers
+                .iter()
+                .for_each(|cond| print!(" {}@{}ms", cond.task.tid, cond.expire_ms));
+            println!();
+            timers.iter().for_each(|cond| dump_stats(&cond.task));
+        }
+        None => println!("timers: locked"),
+    }
+}
+
+/// 打印线程的调度统计, 时间单位为 rdtime 的 tick
+fn dump_stats(task: &Task) {
+    println!("  tid {:>3}: {}", task.tid, task.stats);
+}
+
+/// 取消协程 cid
+///
+/// 调度器不读取执行器的协程表, 不知道协程在哪个线程中, 因此通知所有线程,
+/// 由协程所在的线程丢弃它。至少一个线程收到请求时返回 true, 不代表协程仍存在
+pub fn cancel(current: &Task, cid: usize) -> bool {
+    let mut sent = current.cancel(cid);
+    for_each_task(|t, _| sent |= t.cancel(cid));
+    sent
+}
+
+/// 将 current 及其他线程的信息写入 buf, 返回线程总数
+pub fn task_infos(current: &Task, buf: &mut [TaskInfo]) -> usize {
+    let mut count = 0;
+    let mut write = |t: &Task, state| {
+        if let Some(info) = buf.get_mut(count) {
+            *info = t.info(state);
+        }
+        count += 1;
+    };
+    write(current, TaskState::Running);
+    for_each_task(write);
+    count
+}
+
+pub fn handle_append_task(task: Task, async_task: usize) -> (Task, usize) {
     let mut ret = usize::MAX;
 
-    let future = restore_boxed_PinBoxFuture(future);
+    let async_task = restore_boxed_AsyncTask(async_task);
 
     if task.tid != IO_TASK_TID && task.io {
         if let Some(task) = get_task_by_tid(IO_TASK_TID) {
-            task.append(future);
+            task.append(async_task);
             add_task_to_queue(task);
         }
         ret = IO_TASK_TID;
     } else {
-        task.append(future);
+        task.append(async_task);
         ret = task.tid;
     }
 
     (task, ret)
 }
 
-/// get addr of PinBoxFuture b
-pub fn leak_boxed_PinBoxFuture(b: PinBoxFuture) -> usize {
+/// get addr of AsyncTask b
+pub fn leak_boxed_AsyncTask(b: AsyncTask) -> usize {
     Box::leak(Box::new(b)) as *mut _ as usize
 }
 
-fn restore_boxed_PinBoxFuture(a: usize) -> PinBoxFuture {
-    unsafe { *Box::from_raw(a as *mut PinBoxFuture) }
+fn restore_boxed_AsyncTask(a: usize) -> AsyncTask {
+    unsafe { *Box::from_raw(a as *mut AsyncTask) }
 }
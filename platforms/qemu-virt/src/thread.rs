@@ -2,9 +2,11 @@
 
 extern crate alloc;
 
-use crate::{syscall::sys_exit, STACK_SIZE};
+use crate::{
+    syscall::sys_exit, MIN_STACK_SIZE, STACK_CANARY, STACK_CANARY_WORDS, STACK_SIZE,
+};
 use alloc::{
-    alloc::{alloc, dealloc},
+    alloc::{alloc, dealloc, handle_alloc_error},
     boxed::Box,
     collections::VecDeque,
     fmt, format,
@@ -35,6 +37,8 @@ pub struct TaskControlBlock {
     pub ctx: LocalContext,
     /// 栈底部地址
     pub stack: usize,
+    /// 栈容量
+    pub stack_size: usize,
     /// 返回值
     pub exit_code: Option<i32>,
     /// 状态
@@ -45,7 +49,7 @@ impl fmt::Debug for TaskControlBlock {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("TaskControlBlock")
             .field("context", &self.ctx)
-            .field("stack_top", &format!("{:X}", &(&self.stack + STACK_SIZE)))
+            .field("stack_top", &format!("{:X}", &(&self.stack + self.stack_size)))
             .field("stack_bottom", &format!("{:X}", &self.stack))
             .field("exit_code", &self.exit_code)
             .field("status", &self.status)
@@ -57,17 +61,33 @@ impl TaskControlBlock {
     pub const ZERO: Self = Self {
         ctx: LocalContext::empty(),
         stack: 0,
+        stack_size: 0,
         exit_code: None,
         status: TaskStatus::Blocking,
     };
 
     /// 初始化一个任务。
     pub fn init(&mut self, entry: usize) {
+        self.init_with_stack(entry, STACK_SIZE);
+    }
+
+    /// 初始化一个任务，栈容量为 stack_size (向上对齐至 MIN_STACK_SIZE)。
+    pub fn init_with_stack(&mut self, entry: usize, stack_size: usize) {
+        let stack_size = stack_size
+            .max(MIN_STACK_SIZE)
+            .checked_next_multiple_of(MIN_STACK_SIZE)
+            .expect("stack size overflow");
+        let layout = stack_layout(stack_size);
+        let stack = unsafe { alloc(layout) };
+        if stack.is_null() {
+            handle_alloc_error(layout);
+        }
         self.ctx = LocalContext::thread(entry, true);
-        self.stack =
-            unsafe { alloc(Layout::from_size_align(STACK_SIZE, STACK_SIZE).unwrap()) } as usize;
-        *self.ctx.sp_mut() = self.stack + STACK_SIZE;
+        self.stack = stack as usize;
+        self.stack_size = stack_size;
+        *self.ctx.sp_mut() = self.stack + stack_size;
         self.status = TaskStatus::Blocking;
+        self.set_canary();
     }
 
     /// 初始化一个任务。
@@ -81,10 +101,35 @@ impl TaskControlBlock {
         self.ctx = LocalContext::thread(entry, true);
         let stack = self.stack as *mut u8;
         unsafe {
-            stack.write_bytes(0, STACK_SIZE);
+            stack.write_bytes(0, self.stack_size);
         }
-        *self.ctx.sp_mut() = self.stack + STACK_SIZE;
+        *self.ctx.sp_mut() = self.stack + self.stack_size;
         self.status = TaskStatus::Blocking;
+        self.set_canary();
+    }
+
+    fn canary(&self) -> &[usize] {
+        unsafe { core::slice::from_raw_parts(self.stack as *const usize, STACK_CANARY_WORDS) }
+    }
+
+    /// 在栈底写入金丝雀
+    fn set_canary(&mut self) {
+        unsafe {
+            core::slice::from_raw_parts_mut(self.stack as *mut usize, STACK_CANARY_WORDS)
+                .fill(STACK_CANARY);
+        }
+    }
+
+    /// 检查栈是否溢出: sp 越过栈底金丝雀，或金丝雀被改写
+    pub fn stack_overflowed(&self) -> bool {
+        if self.stack == 0 {
+            return false;
+        }
+        let guard = self.stack + STACK_CANARY_WORDS * core::mem::size_of::<usize>();
+        let sp = self.ctx.sp();
+        sp < guard
+            || sp > self.stack + self.stack_size
+            || self.canary().iter().any(|&w| w != STACK_CANARY)
     }
 
     pub fn move_next(&mut self) {
@@ -102,12 +147,17 @@ impl Drop for TaskControlBlock {
     fn drop(&mut self) {
         if self.stack != 0 {
             unsafe {
-                dealloc(self.stack as *mut u8, Layout::from_size_align(STACK_SIZE, STACK_SIZE).unwrap());
+                dealloc(self.stack as *mut u8, stack_layout(self.stack_size));
             }
         }
     }
 }
 
+#[inline]
+fn stack_layout(stack_size: usize) -> Layout {
+    Layout::from_size_align(stack_size, MIN_STACK_SIZE).unwrap()
+}
+
 struct ThreadRunner<F>
 where
     F: FnOnce() + Send + 'static,
@@ -148,6 +198,14 @@ extern "C" fn run_boxed_thread(arg: usize) {
 
 /// 创建一个线程，并返回 TCBlock
 pub fn spawn<F>(f: F) -> TCBlock
+where
+    F: FnOnce() + Send + 'static,
+{
+    spawn_with_stack(f, STACK_SIZE)
+}
+
+/// 创建一个栈容量为 stack_size 的线程，并返回 TCBlock
+pub fn spawn_with_stack<F>(f: F, stack_size: usize) -> TCBlock
 where
     F: FnOnce() + Send + 'static,
 {
@@ -164,7 +222,11 @@ where
     let box_runner: Box<BoxedThreadRun> = Box::new(Box::new(runner));
     let arg = leak_boxed_thread_run(box_runner);
 
-    t.lock().init_with_arg(run_boxed_thread as usize, arg);
+    {
+        let mut tcb = t.lock();
+        tcb.init_with_stack(run_boxed_thread as usize, stack_size);
+        *tcb.ctx.a_mut(0) = arg;
+    }
 
     t
 }
@@ -2,7 +2,9 @@
 
 extern crate alloc;
 use crate::{
+    board,
     tasks::{add_task_to_queue, Task},
+    trace::{self, SchedEvent},
     trap::*,
     Virt,
     consts::*,
@@ -43,7 +45,8 @@ impl Ord for TimerCondVar {
 pub static TIMERS: Lazy<Mutex<Heap<TimerCondVar>>> =
     Lazy::new(|| Mutex::new(Heap::new()));
 
-pub(crate) fn move_timer(expire_ms: usize, task: Task) {
+pub(crate) fn move_timer(expire_ms: usize, mut task: Task) {
+    task.stats.wait_since = time::read();
     TIMERS.lock().push(TimerCondVar { expire_ms, task });
 }
 
@@ -53,8 +56,11 @@ pub(crate) fn check_timer() {
     let mut timers = TIMERS.lock();
     while let Some(cond) = timers.peek() {
         if cond.expire_ms <= current_ms {
-            if let Some(task) = timers.pop() {
-                add_task_to_queue(task.task);
+            if let Some(cond) = timers.pop() {
+                let mut task = cond.task;
+                task.stats.timer_wait += time::read() - task.stats.wait_since;
+                trace::record(task.tid, SchedEvent::Wakeup);
+                add_task_to_queue(task);
             }
         } else {
             break;
@@ -64,17 +70,18 @@ pub(crate) fn check_timer() {
 
 /// get current time in microseconds
 pub fn get_time_us() -> usize {
-    (time::read() / (CLOCK_FREQ / MICRO_PER_SEC)) as usize
+    (time::read() / (board::timebase() / MICRO_PER_SEC)) as usize
 }
 
 /// get current time in milliseconds
 pub fn get_time_ms() -> usize {
-    (time::read() / (CLOCK_FREQ / MILLI_PER_SEC)) as usize
+    (time::read() / (board::timebase() / MILLI_PER_SEC)) as usize
 }
 
 /// sleep current task 设计成中断
 pub fn sleep(task: Task, ms: usize) -> (Option<Task>, usize) {
     let expire_ms = get_time_ms() + ms;
+    trace::record(task.tid, SchedEvent::Sleep(ms));
     move_timer(expire_ms, task);
     (None, 0)
 }
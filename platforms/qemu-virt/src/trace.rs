@@ -0,0 +1,143 @@
+#![allow(unused)]
+
+use crate::{consts::*, Virt};
+use core::fmt;
+use platform::Platform;
+use spin::Mutex;
+use stdio::println;
+
+/// 线程统计信息，时间单位为 rdtime 的 tick
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TaskStats {
+    /// 累计运行时间
+    pub run_time: usize,
+    /// 获得的时间片数量
+    pub slices: usize,
+    /// 主动让出 CPU 次数 (yield, sleep)
+    pub voluntary: usize,
+    /// 被时钟中断抢占次数
+    pub preempted: usize,
+    /// 协程轮询次数, 来自 Executor::ticks
+    pub polls: usize,
+    /// 被窃取协程的次数
+    pub steals: usize,
+    /// 在 TIMERS 中等待的时间
+    pub timer_wait: usize,
+    /// 进入 TIMERS 的时刻
+    pub(crate) wait_since: usize,
+}
+
+/// 调度事件
+#[derive(Clone, Copy, Debug)]
+pub enum SchedEvent {
+    /// 获得新的时间片
+    Run,
+    /// 时钟中断抢占
+    Preempt,
+    /// 外部中断
+    Irq(u32),
+    /// 系统调用
+    Syscall(usize),
+    /// 协程被窃取到新线程
+    Steal(usize),
+    /// 进入 TIMERS 等待
+    Sleep(usize),
+    /// 从 TIMERS 唤醒
+    Wakeup,
+    /// 线程结束
+    Exit,
+}
+
+#[derive(Clone, Copy)]
+pub struct TraceRecord {
+    pub time: usize,
+    pub tid: usize,
+    pub event: SchedEvent,
+}
+
+impl fmt::Display for TaskStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "run {} slices {} voluntary {} preempted {} polls {} steals {} timer_wait {}",
+            self.run_time,
+            self.slices,
+            self.voluntary,
+            self.preempted,
+            self.polls,
+            self.steals,
+            self.timer_wait
+        )
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>12} {:>4} {:?}", self.time, self.tid, self.event)
+    }
+}
+
+/// 调度事件环形缓冲区，写满后覆盖最旧的事件
+struct TraceRing {
+    records: [TraceRecord; TRACE_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl TraceRing {
+    const EMPTY: TraceRecord = TraceRecord {
+        time: 0,
+        tid: 0,
+        event: SchedEvent::Exit,
+    };
+
+    const fn new() -> Self {
+        Self {
+            records: [Self::EMPTY; TRACE_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, record: TraceRecord) {
+        let tail = (self.head + self.len) % TRACE_SIZE;
+        self.records[tail] = record;
+        if self.len == TRACE_SIZE {
+            self.head = (self.head + 1) % TRACE_SIZE;
+        } else {
+            self.len += 1;
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &TraceRecord> {
+        (0..self.len).map(move |i| &self.records[(self.head + i) % TRACE_SIZE])
+    }
+}
+
+/// TRACE: 只在调度器或关闭中断时访问
+static TRACE: Mutex<TraceRing> = Mutex::new(TraceRing::new());
+
+/// 记录一个调度事件
+#[inline]
+pub fn record(tid: usize, event: SchedEvent) {
+    TRACE.lock().push(TraceRecord {
+        time: Virt::rdtime(),
+        tid,
+        event,
+    });
+}
+
+/// 打印并清空调度事件, 调用者需要关闭中断
+pub fn dump() {
+    // panic 后关机时, 锁可能被 panic 的一方持有
+    let Some(mut trace) = TRACE.try_lock() else {
+        println!("==== sched trace locked ====");
+        return;
+    };
+    println!("==== sched trace ({} events) ====", trace.len);
+    for record in trace.iter() {
+        println!("{record}");
+    }
+    trace.head = 0;
+    trace.len = 0;
+}
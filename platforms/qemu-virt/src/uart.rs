@@ -0,0 +1,114 @@
+#![allow(unused)]
+
+//! 串口接收中断, 收到的字符放入环形缓冲区, 读者在串口中断号上等待
+
+use crate::{
+    board::board,
+    irq,
+    trap::{pop_on, push_off},
+};
+use core::{
+    ptr::{read_volatile, write_volatile},
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+use spin::Mutex;
+
+// 16550 寄存器偏移, qemu virt 的 reg-shift 为 0
+const RBR: usize = 0;
+const IER: usize = 1;
+const LSR: usize = 5;
+/// IER: 接收数据可用中断
+const IER_RX_AVAILABLE: u8 = 0x01;
+/// LSR: 接收缓冲区有数据
+const LSR_DATA_READY: u8 = 0x01;
+
+const RX_BUF_SIZE: usize = 256;
+
+struct RingBuffer {
+    buf: [u8; RX_BUF_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl RingBuffer {
+    const fn new() -> Self {
+        Self {
+            buf: [0; RX_BUF_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// 缓冲区满时丢弃并返回 false
+    fn push(&mut self, c: u8) -> bool {
+        if self.len == RX_BUF_SIZE {
+            return false;
+        }
+        self.buf[(self.head + self.len) % RX_BUF_SIZE] = c;
+        self.len += 1;
+        true
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let c = self.buf[self.head];
+        self.head = (self.head + 1) % RX_BUF_SIZE;
+        self.len -= 1;
+        Some(c)
+    }
+}
+
+static RX: Mutex<RingBuffer> = Mutex::new(RingBuffer::new());
+
+/// 缓冲区满时丢弃的字符数
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+#[inline]
+fn reg(offset: usize) -> *mut u8 {
+    (board().uart + offset) as *mut u8
+}
+
+/// 打开接收中断, 需要在 board::init 之后调用
+pub fn init() {
+    irq::register_handler(board().uart_irq, handle_interrupt);
+    unsafe { write_volatile(reg(IER), IER_RX_AVAILABLE) };
+}
+
+/// 在调度器中处理串口中断, 读空硬件 FIFO; 等待者由 irq::dispatch 唤醒
+fn handle_interrupt() {
+    let mut rx = RX.lock();
+    while unsafe { read_volatile(reg(LSR)) } & LSR_DATA_READY != 0 {
+        let c = unsafe { read_volatile(reg(RBR)) };
+        if !rx.push(c) {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// 从缓冲区取一个字符
+pub fn try_getchar() -> Option<u8> {
+    // 线程持锁时不能被调度器打断
+    let sstatus = push_off();
+    let c = RX.lock().pop();
+    pop_on(sstatus);
+    c
+}
+
+/// 没有字符时登记 waker, 期间到来的中断会立即唤醒
+pub fn poll_getchar(cx: &mut Context<'_>) -> Poll<u8> {
+    let irq = board().uart_irq;
+    let seq = irq::seq(irq);
+    if let Some(c) = try_getchar() {
+        return Poll::Ready(c);
+    }
+    irq::register_waker(irq, seq, cx.waker());
+    Poll::Pending
+}
+
+/// 缓冲区满时丢弃的字符数
+pub fn dropped() -> usize {
+    DROPPED.load(Ordering::Relaxed)
+}
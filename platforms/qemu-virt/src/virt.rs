@@ -1,69 +1,158 @@
 extern crate alloc;
 extern crate timer;
 
-use crate::{consts::*, e1000, syscall::*, timer::get_time_us};
-use alloc::boxed::Box;
-use core::{future::Future, task::Context};
-use executor::IRQ;
-use platform::Platform;
-use qemu_virt_ld as linker;
+use crate::{
+    async_executor, board, e1000,
+    consts::*,
+    mm, net, process,
+    syscall::*,
+    tasks,
+    timer::get_time_us,
+    trace,
+    trap::{pop_on, push_off},
+    irq, uart, virtio_blk, virtio_net,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    future::Future,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+};
+use platform::{AllocRecord, HeapStats, Platform, TaskInfo};
 use sbi_rt::*;
-use spin::Once;
+use spin::{Mutex, Once};
+use stdio::log;
 use uart_16550::MmioSerialPort;
 
 pub struct Virt;
 
-// unsafe: 暂时未找到使用 Mutex 很好的办法
-// 1. 自定义 Mutex, 在 lock 失败时让出 CPU
-// 2. 使用 try_lock, lock 失败让出 CPU
-static mut UART0: Once<MmioSerialPort> = Once::new();
+static UART0: Once<Mutex<MmioSerialPort>> = Once::new();
+/// force_sync 之后不再等待串口锁
+static UART_FORCED: AtomicBool = AtomicBool::new(false);
 
 pub fn init(uart: MmioSerialPort) {
-    unsafe {
-        UART0.call_once(|| uart);
+    UART0.call_once(|| Mutex::new(uart));
+}
+
+/// 关闭中断后持有串口锁, 调度器中的输出不会等待被打断的持锁线程
+///
+/// force_sync 之后拿不到锁时直接写寄存器: panic 可能发生在持锁期间
+fn with_uart(f: impl FnOnce(&mut MmioSerialPort)) {
+    let Some(uart) = UART0.get() else {
+        return;
+    };
+    let sstatus = push_off();
+    match uart.try_lock() {
+        Some(mut uart) => f(&mut uart),
+        None if UART_FORCED.load(Ordering::Acquire) => {
+            f(&mut unsafe { MmioSerialPort::new(board::board().uart) })
+        }
+        None => f(&mut uart.lock()),
     }
+    pop_on(sstatus);
 }
 
 impl platform::Platform for Virt {
     #[inline]
     fn console_getchar() -> u8 {
-        unsafe { UART0.get_mut().unwrap().receive() }
+        // 接收中断填充缓冲区, 没有输入时让出 CPU
+        loop {
+            if let Some(c) = uart::try_getchar() {
+                return c;
+            }
+            sys_yield();
+        }
     }
 
     #[inline]
     fn console_putchar(c: u8) {
-        unsafe {
-            UART0.get_mut().unwrap().send(c);
-        }
+        with_uart(|uart| uart.send(c));
+    }
+
+    #[inline]
+    fn console_put_str(s: &str) {
+        with_uart(|uart| s.bytes().for_each(|c| uart.send(c)));
+    }
+
+    #[inline]
+    fn console_irq() -> Option<u32> {
+        Some(board::board().uart_irq)
+    }
+
+    fn net_irq() -> Option<u32> {
+        let irq = match net::nic()? {
+            net::Nic::E1000 => e1000::irq(),
+            net::Nic::VirtIO => virtio_net::irq(),
+        };
+        // PLIC 中断号 0 保留, 表示设备没有中断
+        Some(irq).filter(|&irq| irq != 0)
+    }
+
+    fn blk_irq() -> Option<u32> {
+        Some(virtio_blk::irq()).filter(|&irq| irq != 0)
     }
 
     #[inline]
     fn net_receive(buf: &mut [u8]) -> usize {
-        e1000::recv(buf)
+        net::recv(buf)
     }
 
     #[inline]
     fn net_transmit(buf: &mut [u8]) {
-        e1000::send(buf);
+        net::send(buf);
     }
 
     #[inline]
     fn net_can_send() -> bool {
-        e1000::can_send()
+        net::can_send()
     }
 
     #[inline]
     fn net_can_recv() -> bool {
-        e1000::can_recv()
+        net::can_recv()
+    }
+
+    #[inline]
+    fn blk_sectors() -> u64 {
+        virtio_blk::sectors()
+    }
+
+    #[inline]
+    fn blk_submit(sector: u64, buf: Vec<u8>, write: bool) -> Result<usize, Vec<u8>> {
+        virtio_blk::submit(sector, buf, write)
+    }
+
+    #[inline]
+    fn blk_poll(id: usize, waker: &Waker) -> Option<(Vec<u8>, bool)> {
+        virtio_blk::poll(id, waker)
+    }
+
+    #[inline]
+    fn blk_forget(id: usize) {
+        virtio_blk::forget(id);
     }
 
     // thread
     #[inline]
-    fn spawn<F>(f: F, is_io: bool) -> usize
+    fn spawn<F>(f: F, is_io: bool, priority: usize) -> usize
     where
         F: Future<Output = ()> + Send + 'static,
     {
-        sys_spawn(f, is_io)
+        sys_spawn(f, is_io, priority, STACK_SIZE)
+    }
+
+    #[inline]
+    fn spawn_with_stack<F>(f: F, is_io: bool, priority: usize, stack_size: usize) -> usize
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let stack_size = if stack_size == 0 { STACK_SIZE } else { stack_size };
+        sys_spawn(f, is_io, priority, stack_size)
+    }
+
+    #[inline]
+    fn set_priority(tid: usize, priority: usize) -> bool {
+        sys_set_priority(tid, priority) == 0
     }
 
     // append_task to current thread
@@ -74,6 +163,35 @@ impl platform::Platform for Virt {
         sys_append_task(f)
     }
 
+    #[inline]
+    fn append_cancellable<F>(f: F) -> (usize, usize)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        sys_append_cancellable(f)
+    }
+
+    #[inline]
+    fn kill(tid: usize) -> bool {
+        sys_kill(tid) == 0
+    }
+
+    #[inline]
+    fn cancel(cid: usize) -> bool {
+        sys_cancel(cid) == 0
+    }
+
+    #[inline]
+    fn tasks(buf: &mut [TaskInfo]) -> usize {
+        sys_tasks(buf)
+    }
+
+    fn exec(elf: &[u8], argv: &[&str], envp: &[&str]) -> Option<usize> {
+        process::exec(elf, argv, envp)
+            .map_err(|err| log::error!("exec {:?}: {err}", argv.first()))
+            .ok()
+    }
+
     #[inline]
     fn wait(_delay: core::time::Duration) {
         sys_sleep(_delay.as_millis() as _);
@@ -86,13 +204,32 @@ impl platform::Platform for Virt {
 
     #[inline]
     fn heap() -> (usize, usize) {
-        let layout = linker::KernelLayout::locate();
-        (layout.end(), MEMORY_SIZE - layout.len())
+        mm::heap()
+    }
+
+    #[inline]
+    fn heap_stats() -> HeapStats {
+        mm::heap_stats()
+    }
+
+    #[inline]
+    fn heap_track(enable: bool) {
+        mm::heap_track(enable);
+    }
+
+    #[inline]
+    fn heap_records(buf: &mut [AllocRecord]) -> usize {
+        mm::heap_records(buf)
+    }
+
+    #[inline]
+    fn heap_dump() {
+        mm::heap_dump();
     }
 
     #[inline]
     fn frequency() -> usize {
-        CLOCK_FREQ
+        board::timebase()
     }
 
     #[inline]
@@ -100,8 +237,26 @@ impl platform::Platform for Virt {
         riscv::register::time::read()
     }
 
+    #[inline]
+    fn trace_dump() {
+        let sstatus = push_off();
+        trace::dump();
+        pop_on(sstatus);
+    }
+
+    #[inline(always)]
+    fn backtrace(buf: &mut [usize]) -> usize {
+        mm::backtrace(0, buf)
+    }
+
+    #[inline]
+    fn sched_dump() {
+        tasks::dump();
+    }
+
     #[inline]
     fn shutdown(error: bool) {
+        Self::trace_dump();
         if error {
             system_reset(Shutdown, SystemFailure);
         } else {
@@ -126,6 +281,26 @@ impl stdio::Stdio for Stdio {
     fn get_char(&self) -> u8 {
         Virt::console_getchar()
     }
+
+    #[inline]
+    fn poll_char(&self, cx: &mut Context<'_>) -> Poll<u8> {
+        uart::poll_getchar(cx)
+    }
+
+    #[inline]
+    fn time_us(&self) -> Option<usize> {
+        Some(get_time_us())
+    }
+
+    #[inline]
+    fn tid(&self) -> Option<usize> {
+        tasks::current_tid()
+    }
+
+    #[inline]
+    fn force_sync(&self) {
+        UART_FORCED.store(true, Ordering::Release);
+    }
 }
 
 pub struct Executor;
@@ -135,22 +310,19 @@ impl executor::Executor for Executor {
     }
 
     fn sys_spawn(&self, f: Box<dyn FnOnce() + Send>, is_io: bool) {
-        Virt::spawn(async { f() }, is_io);
+        Virt::spawn(async { f() }, is_io, DEFAULT_PRIORITY);
     }
 
     fn sys_yield(&self) {
         Virt::sys_yield();
     }
 
-    fn sys_register_irq(&self, cx: &mut Context<'_>, irq: IRQ) {
-        match irq {
-            IRQ::E1000_IRQ => {
-                e1000::ASYNC_WAIT_WAKER.register(cx.waker());
-            }
-            _ => {
-                cx.waker().wake_by_ref();
-            }
-        }
+    fn sys_irq_seq(&self, irq: u32) -> usize {
+        irq::seq(irq)
+    }
+
+    fn sys_register_irq(&self, cx: &mut Context<'_>, irq: u32, seq: usize) {
+        irq::register_waker(irq, seq, cx.waker());
     }
 }
 
@@ -160,3 +332,23 @@ impl timer::Timer for TimeProvider {
         get_time_us()
     }
 }
+
+pub struct Tracer;
+impl crate::crate_trace::Tracer for Tracer {
+    fn time_us(&self) -> usize {
+        get_time_us()
+    }
+
+    fn tid(&self) -> Option<usize> {
+        tasks::current_tid()
+    }
+
+    fn cid(&self) -> Option<usize> {
+        async_executor::current_cid()
+    }
+
+    // 经过控制台缓冲区, 和日志一起由 xtask decode 挑出
+    fn emit(&self, line: &str) {
+        stdio::print!("{line}");
+    }
+}
@@ -0,0 +1,50 @@
+#![allow(unused)]
+
+//! virtio-mmio 设备探测, DMA 内存来自页帧分配器
+
+use crate::{board::board, frame, virtio_blk, virtio_net, vm::PAGE_SIZE};
+use core::ptr::NonNull;
+use stdio::log;
+use virtio_drivers::{DeviceType, Hal, MmioTransport, Transport, VirtIOHeader};
+
+pub struct HalImpl;
+
+impl Hal for HalImpl {
+    fn dma_alloc(pages: usize) -> usize {
+        frame::dma_alloc(pages * PAGE_SIZE)
+            .map(|(_, paddr)| paddr)
+            .expect("virtio: out of DMA memory")
+    }
+
+    fn dma_dealloc(paddr: usize, pages: usize) -> i32 {
+        frame::dma_dealloc(frame::phys_to_virt(paddr), pages * PAGE_SIZE);
+        0
+    }
+
+    fn phys_to_virt(paddr: usize) -> usize {
+        frame::phys_to_virt(paddr)
+    }
+
+    fn virt_to_phys(vaddr: usize) -> usize {
+        frame::virt_to_phys(vaddr)
+    }
+}
+
+/// 扫描所有 virtio-mmio 槽位并初始化认识的设备, 需要在分页之后调用
+pub fn virtio_init() {
+    for &(addr, irq) in board().virtio_mmio.iter() {
+        let header = NonNull::new(addr as *mut VirtIOHeader).unwrap();
+        // 空槽位的 device id 为 0, 这里会返回错误
+        let Ok(transport) = (unsafe { MmioTransport::new(header) }) else {
+            continue;
+        };
+        match transport.device_type() {
+            DeviceType::Network => virtio_net::init(transport, addr, irq),
+            // 只使用第一个块设备
+            DeviceType::Block if virtio_blk::sectors() == 0 => {
+                virtio_blk::init(transport, addr, irq)
+            }
+            t => log::info!("virtio {addr:#x}: {t:?} unsupported"),
+        }
+    }
+}
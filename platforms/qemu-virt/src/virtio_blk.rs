@@ -0,0 +1,179 @@
+#![allow(unused)]
+
+//! virtio-blk 驱动, 请求异步完成, 由中断唤醒等待者
+
+extern crate alloc;
+
+use crate::{
+    irq,
+    trap::{pop_on, push_off},
+    virtio::HalImpl,
+};
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use core::{
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    task::Waker,
+};
+use spin::Mutex;
+use stdio::log;
+use virtio_drivers::{BlkResp, MmioTransport, RespStatus, VirtIOBlk};
+
+pub const SECTOR_SIZE: usize = 512;
+
+/// 已提交的请求, 完成前 buf 和 resp 不能释放
+struct Inflight {
+    id: usize,
+    buf: Vec<u8>,
+    resp: Box<BlkResp>,
+    waker: Option<Waker>,
+    /// 等待者已放弃, 完成后直接释放
+    forgotten: bool,
+}
+
+struct Blk {
+    dev: VirtIOBlk<HalImpl, MmioTransport>,
+    next_id: usize,
+    /// 描述符 token -> 请求
+    inflight: BTreeMap<u16, Inflight>,
+    /// 请求号 -> (buf, 是否成功)
+    done: BTreeMap<usize, (Vec<u8>, bool)>,
+}
+
+// MmioTransport 持有裸指针, 设备只在 DRIVER 锁内访问
+unsafe impl Send for Blk {}
+
+impl Blk {
+    /// 回收已完成的描述符, 返回需要唤醒的 waker
+    fn reap(&mut self, wakers: &mut Vec<Waker>) {
+        while let Ok(token) = self.dev.pop_used() {
+            let Some(req) = self.inflight.remove(&token) else {
+                log::warn!("virtio-blk: unknown token {token}");
+                continue;
+            };
+            if req.forgotten {
+                continue;
+            }
+            let ok = matches!(req.resp.status(), RespStatus::Ok);
+            self.done.insert(req.id, (req.buf, ok));
+            wakers.extend(req.waker);
+        }
+    }
+}
+
+static DRIVER: Mutex<Option<Blk>> = Mutex::new(None);
+
+// 设备容量, 单位为扇区
+static SECTORS: AtomicU64 = AtomicU64::new(0);
+
+// PLIC 中断号, 来自设备树中的 virtio_mmio 节点
+static IRQ: AtomicU32 = AtomicU32::new(0);
+
+pub fn init(transport: MmioTransport, addr: usize, irq: u32) {
+    // 设备配置空间从 0x100 开始, 第一个字段为 u64 capacity
+    let sectors = unsafe { core::ptr::read_volatile((addr + 0x100) as *const u64) };
+    match VirtIOBlk::<HalImpl, _>::new(transport) {
+        Ok(dev) => {
+            log::info!("virtio-blk {addr:#x}: {sectors} sectors, irq {irq}");
+            *DRIVER.lock() = Some(Blk {
+                dev,
+                next_id: 0,
+                inflight: BTreeMap::new(),
+                done: BTreeMap::new(),
+            });
+            IRQ.store(irq, Ordering::Relaxed);
+            irq::register_handler(irq, handle_interrupt);
+            SECTORS.store(sectors, Ordering::Relaxed);
+        }
+        Err(e) => log::warn!("virtio-blk {addr:#x}: {e:?}"),
+    }
+}
+
+#[inline]
+pub fn irq() -> u32 {
+    IRQ.load(Ordering::Relaxed)
+}
+
+#[inline]
+pub fn sectors() -> u64 {
+    SECTORS.load(Ordering::Relaxed)
+}
+
+#[inline]
+fn with_driver<T>(f: impl FnOnce(&mut Blk) -> T) -> T {
+    // 中断处理在调度器中进行, 线程持锁时不能被打断
+    let sstatus = push_off();
+    let ret = f(DRIVER.lock().as_mut().expect("VirtIOBlk Driver uninit"));
+    pop_on(sstatus);
+    ret
+}
+
+/// 提交单扇区请求, 返回请求号; 队列满时返回 buf
+pub fn submit(sector: u64, mut buf: Vec<u8>, write: bool) -> Result<usize, Vec<u8>> {
+    if buf.len() != SECTOR_SIZE || sector >= sectors() {
+        return Err(buf);
+    }
+    with_driver(|blk| {
+        let mut resp = Box::new(BlkResp::default());
+        let token = unsafe {
+            if write {
+                blk.dev.write_block_nb(sector as usize, &buf, &mut resp)
+            } else {
+                blk.dev.read_block_nb(sector as usize, &mut buf, &mut resp)
+            }
+        };
+        let Ok(token) = token else {
+            return Err(buf);
+        };
+        let id = blk.next_id;
+        blk.next_id += 1;
+        blk.inflight.insert(
+            token,
+            Inflight {
+                id,
+                buf,
+                resp,
+                waker: None,
+                forgotten: false,
+            },
+        );
+        Ok(id)
+    })
+}
+
+/// 取回已完成的请求, 未完成时记录 waker
+pub fn poll(id: usize, waker: &Waker) -> Option<(Vec<u8>, bool)> {
+    let mut wakers = Vec::new();
+    let ans = with_driver(|blk| {
+        // 中断可能还没处理, 先回收一次
+        blk.reap(&mut wakers);
+        let ans = blk.done.remove(&id);
+        if ans.is_none() {
+            if let Some(req) = blk.inflight.values_mut().find(|req| req.id == id) {
+                req.waker = Some(waker.clone());
+            }
+        }
+        ans
+    });
+    wakers.into_iter().for_each(Waker::wake);
+    ans
+}
+
+pub fn forget(id: usize) {
+    with_driver(|blk| {
+        if blk.done.remove(&id).is_none() {
+            if let Some(req) = blk.inflight.values_mut().find(|req| req.id == id) {
+                req.forgotten = true;
+                req.waker = None;
+            }
+        }
+    });
+}
+
+fn handle_interrupt() {
+    let mut wakers = Vec::new();
+    with_driver(|blk| {
+        blk.dev.ack_interrupt();
+        blk.reap(&mut wakers);
+    });
+    wakers.into_iter().for_each(Waker::wake);
+}
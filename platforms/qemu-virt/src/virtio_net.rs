@@ -0,0 +1,74 @@
+#![allow(unused)]
+
+use crate::{
+    irq, net,
+    trap::{pop_on, push_off},
+    virtio::HalImpl,
+};
+use core::sync::atomic::{AtomicU32, Ordering};
+use spin::Mutex;
+use stdio::log;
+use virtio_drivers::{MmioTransport, VirtIONet};
+
+type Net = VirtIONet<HalImpl, MmioTransport>;
+
+/// MmioTransport 持有裸指针, 设备只在 DRIVER 锁内访问
+struct Driver(Net);
+
+unsafe impl Send for Driver {}
+
+static DRIVER: Mutex<Option<Driver>> = Mutex::new(None);
+
+// PLIC 中断号, 来自设备树中的 virtio_mmio 节点
+static IRQ: AtomicU32 = AtomicU32::new(0);
+
+pub fn init(transport: MmioTransport, addr: usize, irq: u32) {
+    match Net::new(transport) {
+        Ok(dev) => {
+            log::info!("virtio-net {addr:#x}: mac {:x?}, irq {irq}", dev.mac());
+            IRQ.store(irq, Ordering::Relaxed);
+            irq::register_handler(irq, handle_interrupt);
+            *DRIVER.lock() = Some(Driver(dev));
+            net::register(net::Nic::VirtIO);
+        }
+        Err(e) => log::warn!("virtio-net {addr:#x}: {e:?}"),
+    }
+}
+
+#[inline]
+pub fn irq() -> u32 {
+    IRQ.load(Ordering::Relaxed)
+}
+
+#[inline]
+fn with_driver<T>(f: impl FnOnce(&mut Net) -> T) -> T {
+    // 中断处理在调度器中进行, 线程持锁时不能被打断
+    let sstatus = push_off();
+    let ret = f(&mut DRIVER.lock().as_mut().expect("VirtIONet Driver uninit").0);
+    pop_on(sstatus);
+    ret
+}
+
+pub fn can_send() -> bool {
+    with_driver(|dev| dev.can_send())
+}
+
+pub fn can_recv() -> bool {
+    with_driver(|dev| dev.can_recv())
+}
+
+pub fn recv(buf: &mut [u8]) -> usize {
+    with_driver(|dev| dev.recv(buf).unwrap_or(0))
+}
+
+pub fn send(buf: &[u8]) {
+    with_driver(|dev| {
+        if let Err(e) = dev.send(buf) {
+            log::warn!("virtio-net send: {e:?}");
+        }
+    });
+}
+
+fn handle_interrupt() {
+    with_driver(|dev| dev.ack_interrupt());
+}
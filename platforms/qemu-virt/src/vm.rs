@@ -0,0 +1,246 @@
+#![allow(unused)]
+
+extern crate alloc;
+
+use crate::board::board;
+use alloc::{boxed::Box, vec::Vec};
+use qemu_virt_ld::KernelLayout;
+use spin::{Mutex, Once};
+use stdio::log;
+
+pub const PAGE_SIZE: usize = 4096;
+const PAGE_BITS: usize = 12;
+const ENTRIES: usize = 512;
+const LEVELS: usize = 3;
+const SATP_SV39: usize = 8 << 60;
+
+/// 页表项标志位
+pub mod flags {
+    pub const V: usize = 1 << 0;
+    pub const R: usize = 1 << 1;
+    pub const W: usize = 1 << 2;
+    pub const X: usize = 1 << 3;
+    pub const U: usize = 1 << 4;
+    pub const G: usize = 1 << 5;
+    pub const A: usize = 1 << 6;
+    pub const D: usize = 1 << 7;
+
+    pub const RX: usize = R | X | A;
+    pub const RO: usize = R | A;
+    pub const RW: usize = R | W | A | D;
+    pub const RWX: usize = R | W | X | A | D;
+}
+
+/// MMIO 区域: (起始地址, 长度), 来自设备树
+fn mmio() -> Vec<(usize, usize)> {
+    let info = board();
+    let mut regions = alloc::vec![
+        (info.uart & !(PAGE_SIZE - 1), PAGE_SIZE),
+        (info.plic.start, info.plic.len()),
+        (info.pci_ecam.start, info.pci_ecam.len()),
+        // PCIe MMIO 窗口, BAR 分配在这里
+        (info.pci_mmio.start, info.pci_mmio.len()),
+    ];
+    regions.extend(
+        info.virtio_mmio
+            .iter()
+            .map(|&(addr, _)| (addr & !(PAGE_SIZE - 1), PAGE_SIZE)),
+    );
+    regions
+}
+
+#[repr(C, align(4096))]
+struct PageTable([usize; ENTRIES]);
+
+impl PageTable {
+    const fn new() -> Self {
+        Self([0; ENTRIES])
+    }
+}
+
+/// Sv39 地址空间
+///
+/// 页表页从内核堆中分配, 内核恒等映射, 因此页表页的虚拟地址即物理地址
+pub struct AddressSpace {
+    root: Box<PageTable>,
+    tables: Vec<Box<PageTable>>,
+}
+
+#[inline]
+fn vpn(vaddr: usize, level: usize) -> usize {
+    (vaddr >> (PAGE_BITS + 9 * level)) & (ENTRIES - 1)
+}
+
+#[inline]
+fn level_size(level: usize) -> usize {
+    PAGE_SIZE << (9 * level)
+}
+
+#[inline]
+fn pte_addr(pte: usize) -> usize {
+    (pte >> 10) << PAGE_BITS
+}
+
+#[inline]
+fn make_pte(paddr: usize, flags: usize) -> usize {
+    ((paddr >> PAGE_BITS) << 10) | flags | flags::V
+}
+
+#[inline]
+fn is_leaf(pte: usize) -> bool {
+    pte & (flags::R | flags::W | flags::X) != 0
+}
+
+impl AddressSpace {
+    /// 创建空地址空间
+    pub fn new() -> Self {
+        Self {
+            root: Box::new(PageTable::new()),
+            tables: Vec::new(),
+        }
+    }
+
+    /// 根页表物理地址
+    #[inline]
+    pub fn root_paddr(&self) -> usize {
+        &*self.root as *const PageTable as usize
+    }
+
+    /// 用于写入 satp 的值
+    #[inline]
+    pub fn satp(&self) -> usize {
+        SATP_SV39 | (self.root_paddr() >> PAGE_BITS)
+    }
+
+    /// 找到 vaddr 在 level 级的页表项, create 为真时创建缺失的中间页表
+    fn find_pte(&mut self, vaddr: usize, level: usize, create: bool) -> Option<&mut usize> {
+        let mut table = self.root_paddr() as *mut PageTable;
+        for l in (level + 1..LEVELS).rev() {
+            let pte = unsafe { &mut (*table).0[vpn(vaddr, l)] };
+            if *pte & flags::V == 0 {
+                if !create {
+                    return None;
+                }
+                let next = Box::new(PageTable::new());
+                *pte = make_pte(&*next as *const PageTable as usize, 0);
+                self.tables.push(next);
+            } else if is_leaf(*pte) {
+                // 已经被大页映射
+                return None;
+            }
+            table = pte_addr(*pte) as *mut PageTable;
+        }
+        Some(unsafe { &mut (*table).0[vpn(vaddr, level)] })
+    }
+
+    /// 将 [vaddr, vaddr + size) 映射到 [paddr, paddr + size), 对齐时使用大页
+    pub fn map(&mut self, vaddr: usize, paddr: usize, size: usize, flags: usize) {
+        assert!(vaddr % PAGE_SIZE == 0 && paddr % PAGE_SIZE == 0);
+        let end = vaddr + size.next_multiple_of(PAGE_SIZE);
+        let (mut va, mut pa) = (vaddr, paddr);
+        while va < end {
+            let level = (0..LEVELS)
+                .rev()
+                .find(|&l| {
+                    let sz = level_size(l);
+                    va % sz == 0 && pa % sz == 0 && end - va >= sz
+                })
+                .unwrap();
+            let pte = self
+                .find_pte(va, level, true)
+                .unwrap_or_else(|| panic!("map {va:#x}: covered by a huge page"));
+            assert!(*pte & flags::V == 0, "map {va:#x}: already mapped");
+            *pte = make_pte(pa, flags);
+            va += level_size(level);
+            pa += level_size(level);
+        }
+    }
+
+    /// 为已映射的 4K 页增加权限, 页未映射或被大页映射时返回 false
+    pub fn add_flags(&mut self, vaddr: usize, flags: usize) -> bool {
+        match self.find_pte(vaddr, 0, false) {
+            Some(pte) if *pte & flags::V != 0 => {
+                *pte |= flags;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// vaddr 所在 4K 页的页表项, 页未映射或被大页映射时返回 None
+    pub fn pte(&self, vaddr: usize) -> Option<usize> {
+        let mut table = self.root_paddr() as *const PageTable;
+        for level in (0..LEVELS).rev() {
+            let pte = unsafe { (*table).0[vpn(vaddr, level)] };
+            if pte & flags::V == 0 {
+                return None;
+            }
+            if is_leaf(pte) {
+                return (level == 0).then_some(pte);
+            }
+            table = pte_addr(pte) as *const PageTable;
+        }
+        None
+    }
+
+    /// 恒等映射
+    #[inline]
+    pub fn map_identity(&mut self, addr: usize, size: usize, flags: usize) {
+        self.map(addr, addr, size, flags);
+    }
+
+    /// 虚拟地址转换为物理地址
+    pub fn translate(&self, vaddr: usize) -> Option<usize> {
+        let mut table = self.root_paddr() as *const PageTable;
+        for level in (0..LEVELS).rev() {
+            let pte = unsafe { (*table).0[vpn(vaddr, level)] };
+            if pte & flags::V == 0 {
+                return None;
+            }
+            if is_leaf(pte) {
+                return Some(pte_addr(pte) + (vaddr & (level_size(level) - 1)));
+            }
+            table = pte_addr(pte) as *const PageTable;
+        }
+        None
+    }
+
+    /// 切换到此地址空间
+    pub unsafe fn activate(&self) {
+        core::arch::asm!("csrw satp, {}", in(reg) self.satp());
+        sfence_vma_all();
+    }
+}
+
+#[inline]
+pub fn sfence_vma_all() {
+    unsafe { core::arch::asm!("sfence.vma") };
+}
+
+/// 内核地址空间
+pub static KERNEL_SPACE: Once<Mutex<AddressSpace>> = Once::new();
+
+/// 建立内核恒等映射并开启分页, [ram_base, ram_base + ram_size) 为内核堆和页帧
+pub fn init(ram_base: usize, ram_size: usize) {
+    let layout = KernelLayout::locate();
+    let mut space = AddressSpace::new();
+    for region in layout.iter() {
+        use qemu_virt_ld::KernelRegionTitle::*;
+        let flags = match region.title {
+            Text => flags::RX,
+            Rodata => flags::RO,
+            Data | Boot => flags::RW,
+        };
+        let start = region.range.start & !(PAGE_SIZE - 1);
+        space.map_identity(start, region.range.end - start, flags | flags::G);
+    }
+    // layout.end() 已按页对齐
+    space.map_identity(ram_base, ram_size, flags::RW | flags::G);
+    for (base, size) in mmio() {
+        space.map_identity(base, size, flags::RW | flags::G);
+    }
+
+    unsafe { space.activate() };
+    log::info!("paging on, satp {:#x}", space.satp());
+    KERNEL_SPACE.call_once(|| Mutex::new(space));
+}
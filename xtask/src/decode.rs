@@ -0,0 +1,145 @@
+//! 从 qemu 的控制台输出中挑出 trace 事件, 转为 CSV
+
+use std::collections::BTreeMap;
+use trace::Record;
+
+/// 解码 `@T` 开头的行, 其余输出忽略; 事件类型有名字时用名字代替编号
+pub fn decode(log: &str) -> String {
+    let mut kinds = BTreeMap::new();
+    let mut records = Vec::new();
+    for line in log.lines() {
+        // 记录可能跟在没有换行的输出后面, 如 shell 提示符
+        let Some(pos) = line.find("@T") else {
+            continue;
+        };
+        let line = line[pos + 2..].trim_end();
+        if let Some(def) = line.strip_prefix("K,") {
+            if let Some((kind, name)) = def.split_once(',') {
+                if let Ok(kind) = kind.parse::<u32>() {
+                    kinds.insert(kind, name.to_string());
+                }
+            }
+        } else if let Some(csv) = line.strip_prefix("C,") {
+            match parse_csv(csv) {
+                Some(record) => records.push(record),
+                None => eprintln!("bad trace record: {line}"),
+            }
+        } else if let Some(hex) = line.strip_prefix('B') {
+            match parse_hex(hex).as_deref().and_then(Record::decode) {
+                Some(record) => records.push(record),
+                None => eprintln!("bad trace record: {line}"),
+            }
+        }
+    }
+
+    let mut csv = String::from("time_us,tid,cid,kind,payload\n");
+    for record in records {
+        let id = |v: Option<u32>| v.map_or(String::new(), |v| v.to_string());
+        let kind = kinds
+            .get(&record.kind)
+            .cloned()
+            .unwrap_or_else(|| record.kind.to_string());
+        csv.push_str(&format!(
+            "{},{},{},{kind},{}\n",
+            record.time_us,
+            id(record.tid),
+            id(record.cid),
+            record.payload
+        ));
+    }
+    csv
+}
+
+fn parse_csv(csv: &str) -> Option<Record> {
+    let fields = csv.split(',').map(str::trim).collect::<Vec<_>>();
+    let [time_us, tid, cid, kind, payload] = fields[..] else {
+        return None;
+    };
+    // 没有线程或协程时为 -1
+    let id = |v: &str| match v {
+        "-1" => Some(None),
+        v => v.parse::<u32>().ok().map(Some),
+    };
+    Some(Record {
+        time_us: time_us.parse().ok()?,
+        tid: id(tid)?,
+        cid: id(cid)?,
+        kind: kind.parse().ok()?,
+        payload: payload.parse().ok()?,
+    })
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let record = Record {
+            time_us: 1_500_000,
+            tid: Some(3),
+            cid: None,
+            kind: 2,
+            payload: 42,
+        };
+        assert_eq!(Some(record), parse_csv("1500000,3,-1,2,42"));
+        assert_eq!(Some(record), parse_csv(" 1500000, 3, -1, 2, 42"));
+
+        let max = parse_csv("18446744073709551615,4294967295,-1,4294967295,18446744073709551615");
+        assert_eq!(
+            Some(Record {
+                time_us: u64::MAX,
+                tid: Some(u32::MAX),
+                cid: None,
+                kind: u32::MAX,
+                payload: u64::MAX,
+            }),
+            max
+        );
+
+        // 只有 -1 表示没有线程或协程
+        assert_eq!(None, parse_csv("0,-2,0,0,0"));
+        assert_eq!(None, parse_csv("0,4294967296,0,0,0"));
+        assert_eq!(None, parse_csv("0,0,0,0"));
+        assert_eq!(None, parse_csv("0,0,0,0,0,0"));
+        assert_eq!(None, parse_csv("0,0,0,x,0"));
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(Some(vec![0x00, 0xab, 0xFF]), parse_hex("00abFF"));
+        assert_eq!(Some(vec![]), parse_hex(""));
+        assert_eq!(None, parse_hex("abc"));
+        assert_eq!(None, parse_hex("0g"));
+        assert_eq!(None, parse_hex("\u{e9}\u{e9}"));
+    }
+
+    #[test]
+    fn test_decode() {
+        let record = Record {
+            time_us: 7,
+            tid: None,
+            cid: Some(5),
+            kind: 1,
+            payload: 9,
+        };
+        let hex: String = record.encode().iter().map(|b| format!("{b:02x}")).collect();
+        let log = format!(
+            "[  0.000001] [INFO] boot\n@TK,1,alloc_small_us\n> @TC,3,1,-1,2,8\n@TB{hex}\n@TBzz\n"
+        );
+        assert_eq!(
+            "time_us,tid,cid,kind,payload\n3,1,,2,8\n7,,5,alloc_small_us,9\n",
+            decode(&log)
+        );
+    }
+}
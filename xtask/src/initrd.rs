@@ -0,0 +1,69 @@
+//! 将主机目录打包为 cpio (newc) 格式的 initrd
+
+use std::{fs, path::Path};
+
+/// 打包 dir 下的所有文件和目录, 路径相对于 dir
+pub fn pack(dir: &Path) -> Vec<u8> {
+    let mut archive = Vec::new();
+    let mut ino = 1;
+    walk(dir, dir, &mut archive, &mut ino);
+    append(&mut archive, "TRAILER!!!", 0, &[], 0);
+    archive
+}
+
+fn walk(root: &Path, dir: &Path, archive: &mut Vec<u8>, ino: &mut u32) {
+    let mut entries = fs::read_dir(dir)
+        .unwrap_or_else(|_| panic!("initrd {} not exist", dir.display()))
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    // 保证打包结果稳定
+    entries.sort();
+    for path in entries {
+        let name = path
+            .strip_prefix(root)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .replace('\\', "/");
+        *ino += 1;
+        if path.is_dir() {
+            append(archive, &name, 0o040755, &[], *ino);
+            walk(root, &path, archive, ino);
+        } else {
+            append(archive, &name, 0o100644, &fs::read(&path).unwrap(), *ino);
+        }
+    }
+}
+
+fn append(archive: &mut Vec<u8>, name: &str, mode: u32, data: &[u8], ino: u32) {
+    let nlink = if mode & 0o040000 != 0 { 2 } else { 1 };
+    let fields = [
+        ino,
+        mode,
+        0,
+        0,
+        nlink,
+        0,
+        data.len() as u32,
+        0,
+        0,
+        0,
+        0,
+        name.len() as u32 + 1,
+        0,
+    ];
+    archive.extend_from_slice(b"070701");
+    for field in fields {
+        archive.extend_from_slice(format!("{field:08x}").as_bytes());
+    }
+    archive.extend_from_slice(name.as_bytes());
+    archive.push(0);
+    pad(archive);
+    archive.extend_from_slice(data);
+    pad(archive);
+}
+
+#[inline]
+fn pad(archive: &mut Vec<u8>) {
+    archive.resize(archive.len().next_multiple_of(4), 0);
+}
@@ -1,6 +1,10 @@
 #[macro_use]
 extern crate clap;
 
+mod decode;
+mod initrd;
+mod symbols;
+
 use clap::Parser;
 use once_cell::sync::Lazy;
 use os_xtask_utils::{BinUtil, Cargo, CommandExt, Qemu};
@@ -26,6 +30,7 @@ enum Commands {
     Asm(BuildArgs),
     Guest(BuildArgs),
     Qemu(BuildArgs),
+    Decode(DecodeArgs),
 }
 
 fn main() {
@@ -37,6 +42,28 @@ fn main() {
         Asm(args) => args.asm(),
         Guest(args) => args.guest(),
         Qemu(args) => args.qemu(),
+        Decode(args) => args.decode(),
+    }
+}
+
+#[derive(Args)]
+struct DecodeArgs {
+    /// 保存的 qemu 控制台输出
+    log: PathBuf,
+    /// 输出的 CSV, 缺省时打印到标准输出
+    #[clap(long, short)]
+    output: Option<PathBuf>,
+}
+
+impl DecodeArgs {
+    fn decode(&self) {
+        let log = fs::read(&self.log)
+            .unwrap_or_else(|_| panic!("log {} not exist", self.log.display()));
+        let csv = decode::decode(&String::from_utf8_lossy(&log));
+        match &self.output {
+            Some(output) => fs::write(output, csv).unwrap(),
+            None => print!("{csv}"),
+        }
     }
 }
 
@@ -53,6 +80,21 @@ struct BuildArgs {
     log: Option<String>,
     #[clap(long)]
     gdb: Option<u16>,
+    /// 打包进内核镜像的用户 ELF, 启动时依次执行
+    #[clap(long)]
+    elf: Vec<PathBuf>,
+    /// 打包为 initrd 链接进内核镜像的目录, 通过 ramfs 只读访问
+    #[clap(long)]
+    initrd: Option<PathBuf>,
+    /// qemu 网卡: e1000 (PCIe) 或 virtio (virtio-mmio)
+    #[clap(long, default_value = "e1000")]
+    net: String,
+    /// 作为 virtio-blk 挂载的 raw 磁盘镜像, 不存在时创建 FAT32 镜像
+    #[clap(long)]
+    disk: Option<PathBuf>,
+    /// qemu 退出后将磁盘镜像中的文件复制到该目录
+    #[clap(long, requires = "disk")]
+    extract: Option<PathBuf>,
 }
 
 impl BuildArgs {
@@ -87,6 +129,9 @@ platform = {{ path = \"../platforms/{1}\", package = \"{1}\" }}
 stdio = {{ path = \"../common/stdio\" }}
 executor = {{ path = \"../common/executor\" }}
 thread = {{ path = \"../libs/thread\" }}
+mem = {{ path = \"../libs/mem\" }}
+ramfs = {{ path = \"../libs/ramfs\" }}
+block = {{ path = \"../libs/block\" }}
 net = {{ path = \"../libs/net\" }}
 timer = {{ path = \"../common/timer\", features = [] }}
 
@@ -103,19 +148,74 @@ std = []
             ),
         )
         .unwrap();
+        let elfs = self
+            .elf
+            .iter()
+            .map(|elf| {
+                let path = fs::canonicalize(elf)
+                    .unwrap_or_else(|_| panic!("elf {} not exist", elf.display()));
+                path.to_str().unwrap().to_string()
+            })
+            .collect::<Vec<_>>();
+        let initrd = self
+            .initrd
+            .as_ref()
+            .map(|dir| {
+                let out = PROJECT.join("target").join("initrd.cpio");
+                fs::create_dir_all(out.parent().unwrap()).unwrap();
+                fs::write(&out, initrd::pack(dir)).unwrap();
+                out.to_str().unwrap().to_string()
+            })
+            .unwrap_or_default();
         let build_tool: &str = match is_std {
             true => "x86_64-apple-darwin",
             false => "riscv64gc-unknown-none-elf",
         };
-        Cargo::build()
-            .package("obj")
-            .optional(&self.log, |cargo, level| {
-                cargo.env("LOG", level);
-            })
-            .release()
-            .target(build_tool)
-            .invoke();
-        PROJECT.join("target").join(build_tool)
+        let build = |symbols: &str| {
+            Cargo::build()
+                .package("obj")
+                .optional(&self.log, |cargo, level| {
+                    cargo.env("LOG", level);
+                })
+                .env("USER_ELFS", elfs.join(","))
+                .env("INITRD", &initrd)
+                .env("SYMBOLS", symbols)
+                .release()
+                .target(build_tool)
+                .invoke();
+        };
+        let target = PROJECT.join("target").join(build_tool);
+        if is_std {
+            build("");
+            return target;
+        }
+        // panic 回溯的符号表来自构建出的 ELF。符号表在 .text 之后的 .rodata 中,
+        // 改变它一般不影响函数地址, 从上次的符号表开始, 重新构建直到不再变化
+        let path = PROJECT.join("target").join("symbols.txt");
+        let elf = target.join("release").join("obj");
+        let mut table = fs::read_to_string(&path).unwrap_or_default();
+        let mut converged = false;
+        for _ in 0..3 {
+            if fs::read_to_string(&path).ok().as_ref() != Some(&table) {
+                fs::write(&path, &table).unwrap();
+            }
+            build(path.to_str().unwrap());
+            let new = symbols::table(&elf);
+            if new == table {
+                converged = true;
+                break;
+            }
+            table = new;
+        }
+        if !converged {
+            // 内核中嵌入的是上一次的符号表, 回溯中的函数名可能有误; 保存最新的, 下次构建从它开始
+            fs::write(&path, &table).unwrap();
+            eprintln!(
+                "warning: symbol table did not converge after 3 builds, \
+                 panic backtraces may show wrong function names"
+            );
+        }
+        target
     }
 
     fn asm(&self) {
@@ -135,6 +235,14 @@ std = []
     }
 
     fn qemu(&self) {
+        let nic = match self.net.as_str() {
+            "e1000" => "e1000,netdev=net0,bus=pcie.0",
+            "virtio" => "virtio-net-device,netdev=net0",
+            net => panic!("unknown net device {net}"),
+        };
+        if let Some(disk) = self.disk.as_ref().filter(|disk| !disk.exists()) {
+            make_disk(disk);
+        }
         let target = self.make(false);
         let elf = target.join("release").join("obj");
         Qemu::system("riscv64")
@@ -152,14 +260,50 @@ std = []
                 "-object",
                 "filter-dump,id=net0,netdev=net0,file=/Users/jackzhang/packets.pcap",
             ])
-            .args(["-device", "e1000,netdev=net0,bus=pcie.0"])
+            .args(["-device", nic])
+            .optional(&self.disk, |qemu, disk| {
+                qemu.arg("-drive")
+                    .arg(format!("file={},if=none,format=raw,id=hd0", disk.display()))
+                    .args(["-device", "virtio-blk-device,drive=hd0"]);
+            })
             .optional(&self.gdb, |qemu, gdb| {
                 qemu.args(["-S", "-gdb", &format!("tcp::{gdb}")]);
             })
             .invoke();
+        if let (Some(disk), Some(dir)) = (&self.disk, &self.extract) {
+            extract_disk(disk, dir);
+        }
     }
 }
 
+const DISK_SIZE_KB: usize = 64 * 1024;
+
+/// 创建 FAT32 磁盘镜像, 需要 dosfstools
+fn make_disk(disk: &Path) {
+    use std::process::Command;
+    let status = Command::new("mkfs.fat")
+        .args(["-F", "32", "-C"])
+        .arg(disk)
+        .arg(DISK_SIZE_KB.to_string())
+        .status()
+        .expect("mkfs.fat not found, install dosfstools");
+    assert!(status.success(), "mkfs.fat failed");
+}
+
+/// 复制磁盘镜像中的所有文件, 需要 mtools
+fn extract_disk(disk: &Path, dir: &Path) {
+    use std::process::Command;
+    fs::create_dir_all(dir).unwrap();
+    let status = Command::new("mcopy")
+        .args(["-s", "-n", "-i"])
+        .arg(disk)
+        .arg("::*")
+        .arg(dir)
+        .status()
+        .expect("mcopy not found, install mtools");
+    assert!(status.success(), "mcopy failed");
+}
+
 fn objcopy(elf: impl AsRef<Path>, binary: bool) -> PathBuf {
     let elf = elf.as_ref();
     let bin = elf.with_extension("bin");
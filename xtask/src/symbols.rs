@@ -0,0 +1,42 @@
+//! 从内核 ELF 生成 panic 回溯使用的符号表, 需要 cargo-binutils 提供的 rust-nm
+
+use std::{path::Path, process::Command};
+
+/// 每行 "十六进制地址 名字", 按地址升序, 只包含代码段的符号
+pub fn table(elf: &Path) -> String {
+    let output = Command::new("rust-nm")
+        .args(["--defined-only", "--numeric-sort", "--demangle"])
+        .arg(elf)
+        .output()
+        .expect("rust-nm not found, install cargo-binutils");
+    assert!(output.status.success(), "rust-nm failed");
+
+    let mut table = String::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        // "ffffffff80200000 T _start", 名字中可能有空格
+        let mut fields = line.splitn(3, ' ');
+        let (Some(addr), Some(kind), Some(name)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        if !matches!(kind, "T" | "t" | "W" | "w") {
+            continue;
+        }
+        table.push_str(&format!(
+            "{} {}\n",
+            addr.trim_start_matches('0'),
+            strip_hash(name)
+        ));
+    }
+    table
+}
+
+/// 去掉 legacy 修饰名的哈希后缀 "::h0123456789abcdef"
+fn strip_hash(name: &str) -> &str {
+    match name.rsplit_once("::h") {
+        Some((path, hash)) if hash.len() == 16 && hash.bytes().all(|c| c.is_ascii_hexdigit()) => {
+            path
+        }
+        _ => name,
+    }
+}